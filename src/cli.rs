@@ -1,10 +1,19 @@
 // path: crates/mk/src/cli.rs
-use std::{fs, io::{self, Read}, path::PathBuf};
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}};
 
 use anyhow::{bail, Context, Result};
 use clap::{Args, Parser, Subcommand};
 
-use crate::{config::Config, ops, template::ContextVars, templater, error::MkError};
+use crate::{
+    config::{Config, StrictMode},
+    engine::{Syntax, Vars},
+    error::MkError,
+    ops, placeholder, prompt,
+    registry::{self, Entry, Resolved},
+    scaffold::{self, Overwrite, Scaffold},
+    template::ContextVars,
+    templater::{self, MatchReason, UnresolvedToken},
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "mk", about = "Just Make It — fast file/dir creation from templates", version)]
@@ -37,14 +46,27 @@ pub struct Cli {
         #[arg(short = 'f', long)]
         file: bool,
 
-        /// Template name (file in ~/.config/mk/templates) or extension (e.g. rs, py, md)
+        /// Template name (file on the template search path) or extension (e.g. rs, py, md).
+        /// Repeat to concatenate several templates in order
         #[arg(short = 't', long, value_name = "NAME|EXT")]
-        template: Option<String>,
+        template: Vec<String>,
+
+        /// Text inserted between templates combined with several -t
+        #[arg(long, value_name = "TEXT")]
+        separator: Option<String>,
 
         /// Do not apply any external template files/placeholders
         #[arg(long = "no-template")]
         no_template: bool,
 
+        /// Define a template variable (repeatable), e.g. -D NAME=UserProfile
+        #[arg(short = 'D', long = "define", value_name = "KEY=VALUE", value_parser = placeholder::parse_define, global = true)]
+        defines: Vec<(String, String)>,
+
+        /// Load template variables from a TOML or JSON file
+        #[arg(long = "vars-file", value_name = "FILE", global = true)]
+        vars_file: Option<PathBuf>,
+
         /// File mode in octal (Unix only), e.g. 644, 755  [aliases: -c/--chmod]
         #[arg(short = 'm', long = "mode", visible_short_alias = 'c', visible_alias = "chmod", value_name = "OCTAL")]
         mode: Option<String>,
@@ -65,7 +87,7 @@ pub struct Cli {
         #[arg(short = 'l', long = "list", value_name = "FILE")]
         list_file: Option<PathBuf>,
 
-        /// List templates available in ~/.config/mk/templates
+        /// List config templates and templates on the search path
         #[arg(long = "list-templates")]
         list_templates: bool,
 
@@ -73,6 +95,10 @@ pub struct Cli {
         #[arg(long)]
         dry_run: bool,
 
+        /// Check for unresolved placeholders before writing: error (default) or warn [config: strict_placeholders]
+        #[arg(long, value_name = "MODE", num_args = 0..=1, default_missing_value = "error", require_equals = true, global = true)]
+        strict: Option<StrictMode>,
+
         /// Subcommands
         #[command(subcommand)]
         cmd: Option<Cmd>,
@@ -80,12 +106,34 @@ pub struct Cli {
 
 #[derive(Debug, Subcommand)]
 pub enum Cmd {
-    /// List built-in config templates
+    /// List all templates (same as --list-templates)
     Templates,
+    /// Explain which template `mk PATH` would use, without writing anything
+    Which(WhichArgs),
+    /// Print a template's rendered content to stdout without creating anything
+    Render(RenderArgs),
     /// Initialize a default config to $XDG_CONFIG_HOME/mk/config.toml
     Init(InitArgs),
 }
 
+#[derive(Debug, Args)]
+pub struct WhichArgs {
+    /// Template name or extension, as for `mk -t` (repeatable)
+    #[arg(short = 't', long, value_name = "NAME|EXT")]
+    template: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RenderArgs {
+    /// Template name or extension, as for `mk -t`
+    #[arg(value_name = "TEMPLATE")]
+    template: String,
+
+    /// Render as if creating this file (sets file_name, file_stem, ...)
+    #[arg(long = "as", value_name = "PATH")]
+    as_path: Option<PathBuf>,
+}
+
 #[derive(Debug, Args)]
 pub struct InitArgs {
     /// Overwrite existing config
@@ -96,28 +144,26 @@ pub struct InitArgs {
 impl Cli {
     pub fn parse() -> Self { <Self as Parser>::parse() }
 
+    /// Variables from `--vars-file`, overridden by `-D` definitions.
+    fn cli_vars(&self) -> Result<Vars> {
+        let mut vars = match &self.vars_file {
+            Some(path) => placeholder::vars_from_file(path)?,
+            None => Vars::new(),
+        };
+        for (k, v) in &self.defines { vars.insert(k.clone(), v.as_str().into()); }
+        Ok(vars)
+    }
+
     pub fn run(self) -> Result<()> {
         // Flags that don't require targets
-        if self.list_templates {
-            let list = templater::list_templates()?;
-            if list.is_empty() { println!("No templates in ~/.config/mk/templates"); }
-            else {
-                println!("Available templates:");
-                for p in list { println!("  - {}", p.file_name().unwrap().to_string_lossy()); }
-            }
-            return Ok(());
-        }
+        if self.list_templates { return print_templates(); }
 
         // Subcommands that do not require targets
         if let Some(cmd) = &self.cmd {
             match cmd {
-                Cmd::Templates => {
-                    let cfg = Config::load_default()?;
-                    for (name, t) in cfg.templates.iter() {
-                        println!("{name}{}", t.ext.as_deref().map(|e| format!(" (.{e})")).unwrap_or_default());
-                    }
-                    return Ok(());
-                }
+                Cmd::Templates => return print_templates(),
+                Cmd::Which(args) => return self.which(args),
+                Cmd::Render(args) => return self.render(args),
                 Cmd::Init(args) => {
                     Config::write_default(args.force)?;
                     println!("mk: wrote default config to {}", Config::default_path()?.display());
@@ -127,8 +173,9 @@ impl Cli {
         }
 
         let cfg = Config::load_default()?;
+        placeholder::configure(&cfg);
         let parents_flag = self.parents || cfg.auto_create_parents;
-        let ext_check = cfg.extension_check;
+        let vars = self.cli_vars()?;
 
         if self.no_clobber && self.force {
             bail!("--no-clobber and --force are mutually exclusive");
@@ -138,169 +185,512 @@ impl Cli {
             bail!("No targets provided. Try: mk README.md src/main.rs -p -t rs");
         }
 
+        let mut stdin_buf = None;
+        if self.stdin {
+            let mut buf = String::new();
+            io::stdin().read_to_string(&mut buf).context("reading from stdin")?;
+            stdin_buf = Some(buf);
+        }
+
+        let defaults = TargetOpts {
+            template: self.template.clone(),
+            mode: self.mode.clone(),
+            open: self.open,
+            no_template: self.no_template,
+            force: self.force,
+            no_clobber: self.no_clobber,
+        };
+
+        // Render everything first so strict mode can abort before any file is written
+        let mut jobs = Vec::new();
+        let mut unresolved = Vec::new();
+
         // Expand from list file if provided
         if let Some(list_path) = &self.list_file {
             let content = fs::read_to_string(list_path).with_context(|| format!("reading list {}", list_path.display()))?;
-            for line in content.lines() {
+            // List files may use <{&KEY&}> (and blocks) with Lua and -D values
+            let mut list_vars = if cfg.apply_external_placeholders && !self.no_template { placeholder::lua_placeholders_cached()?.clone() } else { Vars::new() };
+            list_vars.extend(vars.clone());
+            let out = templater::render_source(&list_path.display().to_string(), &content, Syntax::ANGLE, &mut list_vars, true)?;
+            unresolved.extend(out.unresolved);
+            for line in out.content.lines() {
                 let line = line.trim();
                 if line.is_empty() || line.starts_with('#') { continue; }
                 let mut parts = line.split_whitespace();
                 let Some(path_str) = parts.next() else { continue };
-                let mut t_override = self.template.clone();
-                let mut mode_override = self.mode.clone();
-                let mut open_override = self.open;
-                let mut no_tmpl_override = self.no_template;
-                let mut force_override = self.force;
-                let mut no_clobber_override = self.no_clobber;
+                let mut opts = defaults.clone();
+                let mut line_templates = Vec::new();
                 for arg in parts {
-                    if let Some(rest) = arg.strip_prefix("-t=").or(arg.strip_prefix("--template=")) { t_override = Some(rest.to_string()); }
-                    else if let Some(rest) = arg.strip_prefix("-c=").or(arg.strip_prefix("--chmod=")) { mode_override = Some(rest.to_string()); }
-                    else if arg == "-o" || arg == "--open" { open_override = true; }
-                    else if arg == "--no-template" { no_tmpl_override = true; }
-                    else if arg == "-y" || arg == "--yes" { force_override = true; no_clobber_override = false; }
-                    else if arg == "-n" || arg == "--no" { no_clobber_override = true; force_override = false; }
+                    if let Some(rest) = arg.strip_prefix("-t=").or(arg.strip_prefix("--template=")) { line_templates.push(rest.to_string()); }
+                    else if let Some(rest) = arg.strip_prefix("-c=").or(arg.strip_prefix("--chmod=")) { opts.mode = Some(rest.to_string()); }
+                    else if arg == "-o" || arg == "--open" { opts.open = true; }
+                    else if arg == "--no-template" { opts.no_template = true; }
+                    else if arg == "-y" || arg == "--yes" { opts.force = true; opts.no_clobber = false; }
+                    else if arg == "-n" || arg == "--no" { opts.no_clobber = true; opts.force = false; }
                 }
-                self.process_single(PathBuf::from(path_str), &cfg, t_override, mode_override.as_deref(), open_override, no_tmpl_override, force_override, no_clobber_override)?;
+                // Templates on a line replace the command line's instead of adding to them
+                if !line_templates.is_empty() { opts.template = line_templates; }
+                jobs.extend(self.plan_target(PathBuf::from(path_str), &cfg, &vars, opts, stdin_buf.as_deref(), &mut unresolved)?);
+            }
+        } else {
+            for target in &self.targets {
+                jobs.extend(self.plan_target(target.clone(), &cfg, &vars, defaults.clone(), stdin_buf.as_deref(), &mut unresolved)?);
             }
-            return Ok(());
         }
 
+        templater::check_unresolved(&unresolved, self.strict.unwrap_or(cfg.strict_placeholders))?;
+
+        for job in jobs {
+            let hooks = job.opts().hooks(&cfg);
+            self.execute(job, parents_flag, hooks)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `target` is created as a directory, and why.
+    fn dir_decision(&self, target: &Path, explicit: Option<&str>, template: Option<&Resolved>, stdin: bool) -> (bool, &'static str) {
+        if self.dir { (true, "-d/--dir was given") }
+        else if self.file { (false, "-f/--file was given") }
+        else if matches!(template, Some(Resolved::Scaffold(_))) { (true, "the template is a directory scaffold") }
+        else if target.is_dir() { (true, "it is an existing directory") }
+        else if explicit.is_some() { (false, "a template was requested with -t") }
+        else if stdin { (false, "content comes from --stdin") }
+        else if template.is_some() { (false, "a template matches its name") }
+        else if target.extension().is_none() { (true, "it has no extension") }
+        else { (false, "it has an extension") }
+    }
+
+    /// `mk which`: report how each target would be resolved.
+    fn which(&self, args: &WhichArgs) -> Result<()> {
+        if self.targets.is_empty() { bail!("No targets provided. Try: mk which src/main.rs -t rs"); }
+        let cfg = Config::load_default()?;
+        if args.template.len() > 1 { return self.which_composed(&cfg, &args.template); }
+        let explicit = args.template.first().map(String::as_str);
         for target in &self.targets {
-            // Decide if this should be a directory or a file
-            let target_exists = target.exists();
-            let treat_as_dir = if self.dir { true }
-            else if self.file { false }
-            else if target_exists && target.is_dir() { true }
-            else if self.template.is_none() && !self.stdin && target.extension().is_none() { true }
-            else { false };
-
-            if treat_as_dir {
-                ops::create_dir(target, parents_flag, self.mode.as_deref(), self.dry_run)?;
-                if self.open { println!("mk: directory created: {}", target.display()); }
-                continue;
+            let found = registry::candidates(&cfg, target, explicit, !self.no_template)?;
+            let chosen = found.chosen();
+            let scaffold = matches!(chosen, Some(Resolved::Scaffold(_)));
+            let (is_dir, why) = self.dir_decision(target, explicit, chosen.as_ref(), self.stdin);
+            println!("{}: {} ({why})", target.display(), if is_dir { "directory" } else { "file" });
+            if is_dir && !scaffold { continue; }
+
+            match explicit {
+                Some(name) => println!("  extension_check: not involved (-t {name})"),
+                None if cfg.extension_check => println!("  extension_check: on"),
+                None => println!("  extension_check: off (templates are only used with -t)"),
             }
-
-            // Prompt if exists
-            if target.exists() && target.is_file() && !self.dry_run {
-                if self.force { /* proceed */ }
-                else if self.no_clobber { println!("mk: exists, skipping {}", target.display()); continue; }
-                else {
-                    eprint!("The file '{}' exists. Overwrite? (y/n) ", target.display());
-                    use std::io::Write as _; io::stderr().flush().ok();
-                    let mut answer = String::new(); io::stdin().read_line(&mut answer)?;
-                    if !matches!(answer.trim(), "y"|"Y") { println!("mk: skipped {}", target.display()); continue; }
+            if self.no_template { println!("  file templates:  skipped (--no-template)"); }
+            for (i, c) in found.list.iter().enumerate() {
+                let label = if i == 0 { "used:           " } else { "also matched:   " };
+                let mut why = c.reason.to_string();
+                if c.rank.default_for && !matches!(c.reason, MatchReason::DefaultFor(_)) { why.push_str(", front matter `default_for`"); }
+                if c.rank.priority != 0 { why.push_str(&format!(", priority {}", c.rank.priority)); }
+                println!("  {label} {} ({why})", c.template.source());
+            }
+            for (path, err) in &found.skipped { println!("  skipped:         {} ({err})", path.display()); }
+            let ties = found.ties();
+            if !ties.is_empty() {
+                println!("  ambiguous: {} templates match equally; the first in lookup order is used (set `default_for` or `priority`)", ties.len());
+            }
+            if found.list.is_empty() {
+                match explicit {
+                    Some(name) => println!("  error: {}", MkError::TemplateNotFound(name.to_string(), registry::suggest(&cfg, name))),
+                    None => println!("  no template matches"),
                 }
             }
+            if self.stdin { println!("  --stdin content is used instead of any template"); }
+        }
+        Ok(())
+    }
 
-            // Build template context + resolve explicit template
-            let mut stdin_buf = String::new();
-            if self.stdin { io::stdin().read_to_string(&mut stdin_buf).context("reading from stdin")?; }
-
-            // Resolve external (file) template and config template
-            let explicit = self.template.as_deref();
-            let ext_template = if !self.no_template {
-                templater::resolve_template_for_input(target, explicit, ext_check)?
-            } else { None };
-
-            let cfg_tmpl = if let Some(name) = explicit {
-                cfg.get_template(name)
-            } else if ext_check {
-                let ext_key = target.extension().map(|e| e.to_string_lossy().to_string());
-                ext_key.as_ref().and_then(|k| cfg.get_template(k))
-            } else {
-                None
-            };
-
-            // If template explicitly requested but not found anywhere, error out
-            if let Some(name) = explicit {
-                if cfg_tmpl.is_none() && ext_template.is_none() {
-                    return Err(MkError::TemplateNotFound(name.to_string()).into());
+    /// `mk which` with several `-t`: the template each name resolves to, in the order they are combined.
+    fn which_composed(&self, cfg: &Config, names: &[String]) -> Result<()> {
+        for target in &self.targets {
+            let (is_dir, why) = self.dir_decision(target, names.first().map(String::as_str), None, self.stdin);
+            println!("{}: {} ({why})", target.display(), if is_dir { "directory" } else { "file" });
+            if is_dir { continue; }
+            for (i, name) in names.iter().enumerate() {
+                let found = registry::candidates(cfg, target, Some(name), !self.no_template)?;
+                match found.list.first() {
+                    Some(c) if matches!(c.template, Resolved::Scaffold(_)) => println!("  error: {} is a directory scaffold and cannot be combined", c.template.source()),
+                    Some(c) => println!("  part {}:         {} ({})", i + 1, c.template.source(), c.reason),
+                    None => println!("  error: {}", MkError::TemplateNotFound(name.to_string(), registry::suggest(cfg, name))),
                 }
             }
+            if self.stdin { println!("  --stdin content is used instead of any template"); }
+        }
+        Ok(())
+    }
 
-            let ctx = ContextVars::from_path(target, cfg.author.as_deref());
-            let content = if self.stdin { Some(stdin_buf.as_str()) } else { None };
-
-            ops::create_file(target, cfg_tmpl, &ctx, parents_flag, true, false, self.mode.as_deref(), content, self.dry_run)?;
+    /// `mk render`: render one template with the same variables as a real run and print it.
+    fn render(&self, args: &RenderArgs) -> Result<()> {
+        if !self.targets.is_empty() { bail!("mk render takes a single template; name the target file with --as"); }
+        let cfg = Config::load_default()?;
+        placeholder::configure(&cfg);
+        let name = &args.template;
+        // Without --as, the template's own name stands in for the file being created
+        let probe = args.as_path.clone().unwrap_or_else(|| PathBuf::from(name));
+        let template = resolve_named(&cfg, &probe, name, self.no_template)?;
+        let target = match (&args.as_path, &template) {
+            (Some(path), _) => path.clone(),
+            (None, Resolved::File(path)) => PathBuf::from(path.file_name().unwrap_or_default()),
+            (None, _) => probe,
+        };
+        let ctx = ContextVars::from_path(&target, cfg.author.as_deref()).with_vars(&self.cli_vars()?);
+        let rendered = templater::render_file(&target, Some(&template), None, &ctx, !self.no_template && cfg.apply_external_placeholders, self.verbose)?;
+        templater::check_unresolved(&rendered.unresolved, self.strict.unwrap_or(cfg.strict_placeholders))?;
+        print!("{}", rendered.content);
+        Ok(())
+    }
 
-            // External template + placeholders
-            if !self.no_template {
-                templater::apply_template_and_placeholders(target, ext_template.as_ref(), cfg.apply_external_placeholders, self.verbose)?;
+    /// Run `mk.hooks.pre_create` for a target, then plan it with the path and templates the hook chose.
+    fn plan_target(
+        &self,
+        mut target: PathBuf,
+        cfg: &Config,
+        vars: &Vars,
+        mut opts: TargetOpts,
+        stdin: Option<&str>,
+        unresolved: &mut Vec<UnresolvedToken>,
+    ) -> Result<Vec<Job>> {
+        if opts.hooks(cfg) {
+            let pre = placeholder::pre_create(&target, &opts.template, vars)?;
+            if let Some(reason) = pre.veto {
+                println!("mk: skipped {}: {reason}", target.display());
+                if !self.dry_run { placeholder::on_skip(&target, "refused")?; }
+                return Ok(vec![]);
             }
+            if let Some(path) = pre.path { target = path; }
+            if let Some(templates) = pre.templates { opts.template = templates; }
+        }
+        self.plan(target, cfg, vars, opts, stdin, unresolved)
+    }
+
+    /// Decide what to do with one target and render its content in memory.
+    fn plan(
+        &self,
+        target: PathBuf,
+        cfg: &Config,
+        vars: &Vars,
+        opts: TargetOpts,
+        stdin: Option<&str>,
+        unresolved: &mut Vec<UnresolvedToken>,
+    ) -> Result<Vec<Job>> {
+        if opts.template.len() > 1 { return self.plan_composed(target, cfg, vars, opts, stdin, unresolved); }
+
+        // One template from the registry: file templates, then config templates
+        let explicit = opts.template.first().map(String::as_str);
+        let found = registry::candidates(cfg, &target, explicit, !opts.no_template)?;
+        if self.verbose {
+            for (path, err) in &found.skipped { eprintln!("mk: warning: skipped template {}: {err}", path.display()); }
+        }
+        let ties = found.ties();
+        if self.verbose && !ties.is_empty() {
+            let names: Vec<String> = ties.iter().map(|c| c.template.source().to_string()).collect();
+            eprintln!("mk: warning: {} templates match {} equally, using the first (set `default_for` or `priority` to choose):\n  {}", ties.len(), target.display(), names.join("\n  "));
+        }
+        let template = found.chosen();
+        let scaffold = match &template { Some(Resolved::Scaffold(root)) => Some(root.clone()), _ => None };
 
-            if self.open { ops::open_in_editor(target, self.editor.as_deref())?; }
+        let (treat_as_dir, _) = self.dir_decision(&target, explicit, template.as_ref(), stdin.is_some());
+        if let Some(root) = scaffold {
+            if !treat_as_dir { bail!("template `{}` is a directory scaffold; {} must be a directory", explicit.unwrap_or(""), target.display()); }
+            return self.plan_scaffold(&root, target, cfg, vars, opts, unresolved);
         }
+        if treat_as_dir { return Ok(vec![Job::Dir { target, opts }]); }
 
-        Ok(())
+        if let (Some(name), None) = (explicit, &template) {
+            let opts = TargetOpts { template: vec![pick_similar(cfg, name)?], ..opts };
+            return self.plan(target, cfg, vars, opts, stdin, unresolved);
+        }
+
+        let ctx = ContextVars::from_path(&target, cfg.author.as_deref()).with_vars(vars);
+        Ok(vec![self.plan_file(target, template.as_slice(), &ctx, cfg, opts, stdin, unresolved)?])
     }
 
-    fn process_single(
+    /// Plan a file made of several `-t` templates, rendered in order and joined with `--separator`.
+    fn plan_composed(
         &self,
         target: PathBuf,
         cfg: &Config,
-        template_override: Option<String>,
-        mode_override: Option<&str>,
-        open_override: bool,
-        no_template_override: bool,
-        force_override: bool,
-            no_clobber_override: bool,
-    ) -> Result<()> {
-        let parents_flag = self.parents || cfg.auto_create_parents;
-        let ext_check = cfg.extension_check;
-
-        // Decide if directory
-        let target_exists = target.exists();
-        let treat_as_dir = if self.dir { true } else if self.file { false } else if target_exists && target.is_dir() { true } else if template_override.is_none() && target.extension().is_none() { true } else { false };
-        if treat_as_dir { ops::create_dir(&target, parents_flag, mode_override, self.dry_run)?; return Ok(()); }
-
-        // Prompt if exists
-        if target.exists() && target.is_file() && !self.dry_run {
-            if force_override { /* proceed */ }
-            else if no_clobber_override { println!("mk: exists, skipping {}", target.display()); return Ok(()); }
-            else {
-                eprint!("The file '{}' exists. Overwrite? (y/n) ", target.display());
-                use std::io::Write as _; io::stderr().flush().ok();
-                let mut answer = String::new(); io::stdin().read_line(&mut answer)?;
-                if !matches!(answer.trim(), "y"|"Y") { println!("mk: skipped {}", target.display()); return Ok(()); }
+        vars: &Vars,
+        opts: TargetOpts,
+        stdin: Option<&str>,
+        unresolved: &mut Vec<UnresolvedToken>,
+    ) -> Result<Vec<Job>> {
+        let mut templates = Vec::new();
+        for name in &opts.template {
+            let template = resolve_named(cfg, &target, name, opts.no_template)?;
+            if let Resolved::Scaffold(root) = &template {
+                bail!("template `{name}` is a directory scaffold ({}) and cannot be combined with other templates", root.display());
             }
+            templates.push(template);
         }
+        let (treat_as_dir, _) = self.dir_decision(&target, opts.template.first().map(String::as_str), None, stdin.is_some());
+        if treat_as_dir { return Ok(vec![Job::Dir { target, opts }]); }
 
-        // Build template context + resolve explicit template
-        let mut stdin_buf = String::new();
-        if self.stdin { io::stdin().read_to_string(&mut stdin_buf).context("reading from stdin")?; }
-
-        // Resolve external (file) template and config template
-        let explicit = template_override.as_deref();
-        let ext_template = if !no_template_override {
-            templater::resolve_template_for_input(&target, explicit, ext_check)?
-        } else { None };
-
-        let cfg_tmpl = if let Some(name) = explicit {
-            cfg.get_template(name)
-        } else if ext_check {
-            let ext_key = target.extension().map(|e| e.to_string_lossy().to_string());
-            ext_key.as_ref().and_then(|k| cfg.get_template(k))
-        } else {
-            None
+        let ctx = ContextVars::from_path(&target, cfg.author.as_deref()).with_vars(vars);
+        Ok(vec![self.plan_file(target, &templates, &ctx, cfg, opts, stdin, unresolved)?])
+    }
+
+    /// Render one file target. Several templates are concatenated in order; the first one that sets a
+    /// mode or an `output` path decides it.
+    #[allow(clippy::too_many_arguments)]
+    fn plan_file(
+        &self,
+        target: PathBuf,
+        templates: &[Resolved],
+        ctx: &ContextVars,
+        cfg: &Config,
+        opts: TargetOpts,
+        stdin: Option<&str>,
+        unresolved: &mut Vec<UnresolvedToken>,
+    ) -> Result<Job> {
+        let apply_placeholders = !opts.no_template && cfg.apply_external_placeholders;
+        let rendered = match templates {
+            [_, _, ..] if stdin.is_none() => {
+                let mut parts = Vec::new();
+                let mut combined = templater::Rendered::default();
+                for template in templates {
+                    let r = templater::render_file(&target, Some(template), None, ctx, apply_placeholders, self.verbose)?;
+                    parts.push(r.content);
+                    combined.unresolved.extend(r.unresolved);
+                    combined.mode = combined.mode.or(r.mode);
+                    combined.output = combined.output.or(r.output);
+                }
+                combined.content = parts.join(self.separator.as_deref().unwrap_or(""));
+                combined
+            }
+            _ => templater::render_file(&target, templates.first(), stdin, ctx, apply_placeholders, self.verbose)?,
         };
+        unresolved.extend(rendered.unresolved);
+
+        // CLI mode takes precedence over template mode
+        let mode = opts.mode.clone().or(rendered.mode);
+        // Front matter `output` relocates the file relative to the requested target's directory
+        let target = match rendered.output {
+            Some(out) => target.parent().unwrap_or(Path::new("")).join(out),
+            None => target,
+        };
+        Ok(Job::File { target, content: rendered.content, mode, opts })
+    }
+
+    /// Plan a directory scaffold: the target directory, then every directory and file of the scaffold
+    /// with `__KEY__` expanded in names. `NAME` defaults to the target directory's name.
+    fn plan_scaffold(
+        &self,
+        root: &Path,
+        target: PathBuf,
+        cfg: &Config,
+        vars: &Vars,
+        opts: TargetOpts,
+        unresolved: &mut Vec<UnresolvedToken>,
+    ) -> Result<Vec<Job>> {
+        if self.verbose { eprintln!("Scaffold applied: {}", root.display()); }
+        let rules = Scaffold::load(root)?;
+        let mut vars = vars.clone();
+        if let Some(name) = target.file_name().and_then(|n| n.to_str()) {
+            vars.entry("NAME".to_string()).or_insert_with(|| name.into());
+        }
+        let ctx = ContextVars::from_path(&target, cfg.author.as_deref()).with_vars(&vars).with_template(Some(root.display().to_string()));
+        let mut names = templater::template_vars(&ctx, !opts.no_template && cfg.apply_external_placeholders)?;
+        // Manifest variables are settled once for the whole scaffold
+        let origin = root.join(scaffold::MANIFEST).display().to_string();
+        let prompts = rules.apply_defaults(&mut names);
+        prompt::fill(&prompts, &mut names).with_context(|| format!("in {origin}"))?;
+        rules.coerce(&mut names).with_context(|| format!("in {origin}"))?;
+        for key in rules.manifest.vars.keys() {
+            if let Some(v) = names.get(key) { vars.insert(key.clone(), v.clone()); }
+        }
 
-        // If template explicitly requested but not found anywhere, error out
-        if let Some(name) = explicit {
-            if cfg_tmpl.is_none() && ext_template.is_none() {
-                return Err(MkError::TemplateNotFound(name.to_string()).into());
+        // Entries inherit the per-target flags, but -m and --open only apply to the target itself
+        let entry_opts = TargetOpts { mode: None, open: false, ..opts.clone() };
+        let mut jobs = vec![Job::Dir { target: target.clone(), opts: TargetOpts { mode: None, ..opts.clone() } }];
+        for entry in templater::scaffold_entries(root)? {
+            if rules.skip(&entry.rel, &names)? {
+                if self.verbose { eprintln!("Skipped {}", entry.rel.display()); }
+                continue;
+            }
+            let mut out = target.clone();
+            for part in entry.rel.iter() {
+                out.push(templater::expand_name(&part.to_string_lossy(), &names)?);
+            }
+            if entry.is_dir {
+                jobs.push(Job::Dir { target: out, opts: entry_opts.clone() });
+            } else {
+                let ctx = ContextVars::from_path(&out, cfg.author.as_deref()).with_vars(&vars);
+                let mut file_opts = TargetOpts { mode: opts.mode.clone(), ..entry_opts.clone() };
+                match rules.overwrite(&entry.rel) {
+                    Overwrite::Ask => {}
+                    Overwrite::Never => { file_opts.force = false; file_opts.no_clobber = true; }
+                    Overwrite::Always => { file_opts.force = true; file_opts.no_clobber = false; }
+                }
+                let source = root.join(&entry.rel);
+                // Images, fonts and other non-text files are copied byte for byte
+                if let Some(content) = templater::binary_content(&source)? {
+                    let mode = file_opts.mode.clone().or_else(|| templater::executable_mode(&source));
+                    jobs.push(Job::Copy { target: out, content, mode, opts: file_opts });
+                    continue;
+                }
+                jobs.push(self.plan_file(out, &[Resolved::File(source)], &ctx, cfg, file_opts, None, unresolved)?);
             }
         }
+        Ok(jobs)
+    }
+
+    /// Write one job. With `hooks`, `mk.hooks.post_create` and `mk.hooks.on_skip` run around files.
+    fn execute(&self, job: Job, parents: bool, hooks: bool) -> Result<()> {
+        match job {
+            Job::Dir { target, opts } => {
+                ops::create_dir(&target, parents, opts.mode.as_deref(), self.dry_run)?;
+                if opts.open { println!("mk: directory created: {}", target.display()); }
+            }
+            Job::File { target, content, mode, opts } => {
+                if !self.may_write(&target, &opts, hooks)? { return Ok(()); }
 
-        let ctx = ContextVars::from_path(&target, cfg.author.as_deref());
-        let content = if self.stdin { Some(stdin_buf.as_str()) } else { None };
+                ops::create_file(&target, content.as_bytes(), parents, true, false, mode.as_deref(), self.dry_run)?;
 
-        ops::create_file(&target, cfg_tmpl, &ctx, parents_flag, true, false, mode_override, content, self.dry_run)?;
+                if hooks && !self.dry_run {
+                    let post = placeholder::post_create(&target, &content)?;
+                    if let Some(new) = post.content.filter(|c| *c != content) {
+                        fs::write(&target, new).with_context(|| format!("writing {}", target.display()))?;
+                    }
+                    for (path, text) in post.write {
+                        if self.may_write(&path, &opts, hooks)? { ops::create_file(&path, text.as_bytes(), parents, true, false, None, false)?; }
+                    }
+                    for (path, text) in post.append { ops::append_file(&path, &text, parents)?; }
+                }
 
-        if !no_template_override {
-            templater::apply_template_and_placeholders(&target, ext_template.as_ref(), cfg.apply_external_placeholders, self.verbose)?;
+                if opts.open { ops::open_in_editor(&target, self.editor.as_deref())?; }
+            }
+            Job::Copy { target, content, mode, opts } => {
+                if !self.may_write(&target, &opts, hooks)? { return Ok(()); }
+                ops::create_file(&target, &content, parents, true, false, mode.as_deref(), self.dry_run)?;
+            }
         }
-
-        if open_override { ops::open_in_editor(&target, self.editor.as_deref())?; }
         Ok(())
     }
+
+    /// Whether `target` may be written: an existing file is overwritten with `-y`, kept with `-n`, and
+    /// otherwise only when the user says so. A kept file is reported to `mk.hooks.on_skip` with `hooks`.
+    fn may_write(&self, target: &Path, opts: &TargetOpts, hooks: bool) -> Result<bool> {
+        if !target.is_file() || self.dry_run || opts.force { return Ok(true); }
+        if opts.no_clobber {
+            println!("mk: exists, skipping {}", target.display());
+            if hooks { placeholder::on_skip(target, "exists")?; }
+            return Ok(false);
+        }
+        eprint!("The file '{}' exists. Overwrite? (y/n) ", target.display());
+        use std::io::Write as _; io::stderr().flush().ok();
+        let mut answer = String::new(); io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y"|"Y") {
+            println!("mk: skipped {}", target.display());
+            if hooks { placeholder::on_skip(target, "declined")?; }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+/// The template `-t name` resolves to.
+fn resolve_named<'a>(cfg: &'a Config, target: &Path, name: &str, no_template: bool) -> Result<Resolved<'a>> {
+    let found = registry::candidates(cfg, target, Some(name), !no_template)?;
+    match found.chosen() {
+        Some(template) => Ok(template),
+        None => resolve_named(cfg, target, &pick_similar(cfg, name)?, no_template),
+    }
+}
+
+/// For a `-t name` that matches nothing: offer similar names on a terminal and return the one picked,
+/// else fail with the suggestions.
+fn pick_similar(cfg: &Config, name: &str) -> Result<String> {
+    let suggestions = registry::suggest(cfg, name);
+    let question = format!("mk: template `{name}` not found. Did you mean:");
+    match prompt::pick(&format!("-t {name}"), &question, &suggestions)? {
+        Some(pick) => Ok(pick),
+        None => Err(MkError::TemplateNotFound(name.to_string(), suggestions).into()),
+    }
+}
+
+/// Print the template registry for `--list-templates` / `mk templates`.
+fn print_templates() -> Result<()> {
+    let cfg = Config::load_default()?;
+    let entries = registry::entries(&cfg)?;
+    if entries.is_empty() { println!("No templates in config.toml or on the template search path"); }
+    else {
+        println!("Available templates:");
+        // Top-level templates first, then one group per namespace
+        let mut namespaces: Vec<&str> = entries.iter().map(Entry::namespace).filter(|ns| !ns.is_empty()).collect();
+        namespaces.sort();
+        namespaces.dedup();
+        for e in entries.iter().filter(|e| e.namespace().is_empty()) { print_entry(e, "  "); }
+        for ns in namespaces {
+            println!("  {ns}/");
+            for e in entries.iter().filter(|e| e.namespace() == ns) { print_entry(e, "    "); }
+        }
+    }
+    Ok(())
+}
+
+fn print_entry(e: &Entry, indent: &str) {
+    let name = e.name.rsplit_once('/').map_or(e.name.as_str(), |(_, n)| n);
+    let ext = if e.ext.is_empty() { String::new() } else { format!(" ({})", e.ext.join(", ")) };
+    let desc = e.description.as_ref().map(|d| format!(" — {d}")).unwrap_or_default();
+    let tags = if e.tags.is_empty() { String::new() } else { format!(" [{}]", e.tags.join(", ")) };
+    let layer = e.layer.map(|l| format!("{l}: ")).unwrap_or_default();
+    let shadowed = e.shadowed_by.map(|l| format!("  [shadowed by {l}]")).unwrap_or_default();
+    println!("{indent}- {name}{ext}{desc}{tags}  ({layer}{}){shadowed}", e.source);
+}
+
+/// Per-target options; list-file lines can override the command-line values.
+#[derive(Debug, Clone)]
+struct TargetOpts {
+    /// `-t` names, combined in order when there are several
+    template: Vec<String>,
+    mode: Option<String>,
+    open: bool,
+    no_template: bool,
+    force: bool,
+    no_clobber: bool,
+}
+
+impl TargetOpts {
+    /// Lua hooks run only when the placeholder script is enabled and the target uses templates.
+    fn hooks(&self, cfg: &Config) -> bool {
+        !self.no_template && cfg.apply_external_placeholders
+    }
+}
+
+/// A fully planned creation; nothing is written until every target has been planned.
+enum Job {
+    Dir { target: PathBuf, opts: TargetOpts },
+    File { target: PathBuf, content: String, mode: Option<String>, opts: TargetOpts },
+    /// A scaffold file that is not text, written as is
+    Copy { target: PathBuf, content: Vec<u8>, mode: Option<String>, opts: TargetOpts },
+}
+
+impl Job {
+    fn opts(&self) -> &TargetOpts {
+        match self { Job::Dir { opts, .. } | Job::File { opts, .. } | Job::Copy { opts, .. } => opts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(no_template: bool) -> TargetOpts {
+        TargetOpts { template: vec![], mode: None, open: false, no_template, force: false, no_clobber: false }
+    }
+
+    #[test]
+    fn hooks_are_off_without_templates() {
+        let on: Config = toml::from_str("").unwrap();
+        let off: Config = toml::from_str("apply_external_placeholders = false").unwrap();
+        assert!(opts(false).hooks(&on));
+        assert!(!opts(true).hooks(&on));
+        assert!(!opts(false).hooks(&off));
+        let job = Job::File { target: PathBuf::from("two.rs"), content: String::new(), mode: None, opts: opts(true) };
+        assert!(!job.opts().hooks(&on));
+    }
 }
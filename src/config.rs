@@ -3,10 +3,85 @@ use std::{collections::BTreeMap, fs, path::PathBuf};
 
 use anyhow::{Context, Result};
 use dirs::config_dir;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 fn default_true() -> bool { true }
 fn default_false() -> bool { false }
+fn is_zero(n: &i32) -> bool { *n == 0 }
+fn default_lua_timeout_ms() -> u64 { 5000 }
+fn default_lua_memory_mb() -> u64 { 64 }
+
+/// What to do when a template leaves `<{&KEY&}>`/`{key}` tokens unresolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StrictMode {
+    /// Leave unresolved tokens in the output silently
+    #[default]
+    Off,
+    /// Print every unresolved token but still create the files
+    Warn,
+    /// List every unresolved token and fail before anything is written
+    Error,
+}
+
+/// What to do when `mk_placeholders.lua` cannot be read or fails while running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LuaErrors {
+    /// Print the error and continue without the script's values
+    Warn,
+    /// Stop before anything is written
+    #[default]
+    Error,
+}
+
+/// `[lua]`: what the placeholder script may use beyond the sandbox, and how long it may run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LuaConfig {
+    /// Capabilities to grant: one function (`os.date`, `io.popen`), a whole library (`os`, `io`,
+    /// `package`), or `require`, `dofile`, `loadfile`
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Wall-clock limit for running the script, and for each later call into it, in milliseconds (0 = none)
+    #[serde(default = "default_lua_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Memory the Lua VM may use, in MiB (0 = none)
+    #[serde(default = "default_lua_memory_mb")]
+    pub memory_mb: u64,
+    /// VM instructions allowed per call, counted in steps of 1000 (0 = none)
+    #[serde(default)]
+    pub instructions: u64,
+}
+
+impl Default for LuaConfig {
+    fn default() -> Self {
+        Self { allow: vec![], timeout_ms: default_lua_timeout_ms(), memory_mb: default_lua_memory_mb(), instructions: 0 }
+    }
+}
+
+/// Accepts `strict_placeholders = true|false` as well as `"off"|"warn"|"error"`.
+fn deserialize_strict<'de, D: Deserializer<'de>>(d: D) -> Result<StrictMode, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr { Bool(bool), Mode(StrictMode) }
+    Ok(match Repr::deserialize(d)? {
+        Repr::Bool(true) => StrictMode::Error,
+        Repr::Bool(false) => StrictMode::Off,
+        Repr::Mode(m) => m,
+    })
+}
+
+/// Accepts `ext = "rs"` as well as a list.
+fn deserialize_ext<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr { One(String), Many(Vec<String>) }
+    Ok(match Option::<Repr>::deserialize(d)? {
+        Some(Repr::One(s)) => vec![s],
+        Some(Repr::Many(v)) => v,
+        None => vec![],
+    })
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -18,18 +93,38 @@ pub struct Config {
     pub auto_create_parents: bool,
     #[serde(default = "default_false")]
     pub extension_check: bool,
-    /// If true, mk will run the external placeholder stage (builtins + Lua) after writing files.
-    /// Set to false to skip `<{&KEY&}>` replacement without disabling external file templates.
+    /// If true, Lua placeholders are loaded and external templates and stdin are rendered.
+    /// Set to false to copy them verbatim without disabling external file templates.
     #[serde(default = "default_true")]
     pub apply_external_placeholders: bool,
+    /// `"error"` (or `true`) fails the run when a placeholder is left unresolved, `"warn"` only reports it.
+    /// `--strict` overrides this per invocation.
+    #[serde(default, deserialize_with = "deserialize_strict")]
+    pub strict_placeholders: StrictMode,
+    /// `"error"` (default) stops on a Lua error in `mk_placeholders.lua`, `"warn"` reports it and
+    /// continues without the script's values.
+    #[serde(default)]
+    pub lua_errors: LuaErrors,
+    #[serde(default)]
+    pub lua: LuaConfig,
+    /// Which template to use when several match, keyed by extension or pattern:
+    /// `[default_for] tsx = "react/component"`. Values are template names as for `-t`.
+    #[serde(default)]
+    pub default_for: BTreeMap<String, String>,
     #[serde(default)]
     pub templates: BTreeMap<String, Template>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Template {
-    /// Optional file extension associated with this template (e.g. "rs")
-    pub ext: Option<String>,
+    /// Extensions or file name patterns this template applies to: `ext = "rs"` or
+    /// `ext = ["ts", "*.test.ts", "Dockerfile", "re:^Makefile(\\..+)?$"]`. A plain word is an extension
+    /// or an exact file name.
+    #[serde(default, deserialize_with = "deserialize_ext", skip_serializing_if = "Vec::is_empty")]
+    pub ext: Vec<String>,
+    /// Among equally specific matches, the highest priority wins (default 0)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
     /// Optional file mode (octal string, Unix only)
     pub mode: Option<String>,
     /// Body content (supports {vars})
@@ -76,15 +171,16 @@ impl Config {
         Ok(())
     }
 
-    pub fn get_template(&self, key: &str) -> Option<&Template> {
+    /// Returns the matching template together with its key in `[templates]`.
+    pub fn get_template(&self, key: &str) -> Option<(&str, &Template)> {
         // prefer explicit name match, then any template whose ext matches key
-        self.templates.get(key).or_else(|| {
-            self.templates.values().find(|t| t.ext.as_deref() == Some(key))
-        })
+        self.templates.get_key_value(key).or_else(|| {
+            self.templates.iter().find(|(_, t)| t.ext.iter().any(|e| e == key))
+        }).map(|(k, t)| (k.as_str(), t))
     }
 
     fn default_with_builtin() -> Self {
-        let mut cfg = Self { author: None, auto_create_parents: default_true(), extension_check: default_false(), apply_external_placeholders: default_true(), templates: Default::default() };
+        let mut cfg = Self { author: None, auto_create_parents: default_true(), extension_check: default_false(), apply_external_placeholders: default_true(), strict_placeholders: StrictMode::Off, lua_errors: LuaErrors::Error, lua: Default::default(), default_for: Default::default(), templates: Default::default() };
         for (k, v) in Self::builtin_templates() {
             cfg.templates.insert(k, v);
         }
@@ -93,9 +189,9 @@ impl Config {
 
     fn builtin_templates() -> Vec<(String, Template)> {
         vec![
-            ("default".into(), Template { ext: None, mode: None, body: "".into() }),
+            ("default".into(), Template { ext: vec![], priority: 0, mode: None, body: "".into() }),
             ("rs".into(), Template {
-                ext: Some("rs".into()), mode: None,
+                ext: vec!["rs".into()], priority: 0, mode: None,
              body: r#"// {file_name} — created {date}
              // Author: {author}
 
@@ -105,15 +201,15 @@ impl Config {
             "#.into()
             }),
             ("sh".into(), Template {
-                ext: Some("sh".into()), mode: Some("755".into()),
+                ext: vec!["sh".into()], priority: 0, mode: Some("755".into()),
              body: "#!/usr/bin/env bash
              set -euo pipefail
 
              ".into(),
             }),
-            ("gd".into(), Template { ext: Some("gd".into()), mode: None, body: "extends Node
+            ("gd".into(), Template { ext: vec!["gd".into()], priority: 0, mode: None, body: "extends Node
                 ".into() }),
-                ("md".into(), Template { ext: Some("md".into()), mode: None, body: "# {file_stem}
+                ("md".into(), Template { ext: vec!["md".into()], priority: 0, mode: None, body: "# {file_stem}
 
                 ".into() }),
         ]
@@ -0,0 +1,804 @@
+// path: crates/mk/src/engine.rs
+//! Single-pass template engine shared by config bodies (`{key}`) and file templates (`<{&KEY&}>`).
+//!
+//! A template is tokenized once into a tree of nodes; rendering walks that tree and
+//! substitutes each variable exactly once. Substituted values are never re-scanned, so a value
+//! that happens to contain `<{&OTHER&}>` is written out verbatim.
+//!
+//! Control flow uses the angle delimiters in every template:
+//! `<{&#if KEY&}>`, `<{&#elif KEY == "x"&}>`, `<{&#else&}>`, `<{&/if&}>` and
+//! `<{&#for item in LIST&}>` ... `<{&/for&}>`. A block tag alone on its line swallows that line.
+//! Values can be piped through filters: `<{&NAME|snake|default:"x"&}>`.
+//! `<{&#prompt NAME "Question" default:"x" choices:"a","b"&}>` declares how to ask for a missing value.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{error::MkError, filters};
+
+const ANGLE_OPEN: &str = "<{&";
+const ANGLE_CLOSE: &str = "&}>";
+
+/// Which token syntaxes a template is scanned for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Syntax {
+    /// `<{&KEY&}>` (file templates, Lua placeholders, control flow)
+    pub angle: bool,
+    /// `{key}` (config.toml template bodies)
+    pub brace: bool,
+}
+
+impl Syntax {
+    pub const ANGLE: Syntax = Syntax { angle: true, brace: false };
+    pub const BOTH: Syntax = Syntax { angle: true, brace: true };
+}
+
+/// A template variable: plain text, a list (for `#for`) or a table of named fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Truthiness for `#if`: empty strings, `false`, `0`, `no` and empty collections are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !matches!(s.trim().to_ascii_lowercase().as_str(), "" | "false" | "0" | "no"),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::List(l) => l.iter().map(Value::to_text).collect::<Vec<_>>().join(", "),
+            Value::Map(_) => String::new(),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self { Value::Str(s) }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self { Value::Str(s.to_string()) }
+}
+
+pub type Vars = HashMap<String, Value>;
+
+/// 1-based position of a token in its template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Text(String),
+    Var(Var),
+    If { branches: Vec<(Cond, Vec<Node>)>, otherwise: Vec<Node> },
+    For { item: String, list: String, body: Vec<Node> },
+    /// Declaration only; renders nothing
+    Prompt(PromptSpec),
+    /// `#block NAME`: a section a child template may replace
+    Block { name: String, body: Vec<Node> },
+    /// `#extends "NAME"`: resolved by the caller, renders nothing
+    Extends { parent: String, pos: Pos },
+    /// `>NAME`: replaced by the partial's nodes before rendering (see [`expand_includes`])
+    Include { name: String, pos: Pos },
+}
+
+/// How to ask the user for a variable that no other source defines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptSpec {
+    pub name: String,
+    pub text: Option<String>,
+    pub default: Option<String>,
+    pub choices: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    /// Dotted path, e.g. `NAME` or `field.type`
+    pub name: String,
+    pub filters: Vec<Filter>,
+    /// Original token text, written back when the variable is unknown
+    pub raw: String,
+    pub pos: Pos,
+    /// Partial or parent template the token was inlined from (see [`set_origin`])
+    pub origin: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// Filters defined outside the engine (e.g. `mk.filters` in `mk_placeholders.lua`).
+/// Returns `None` when `name` is unknown.
+pub trait FilterHost {
+    fn call(&self, name: &str, input: &Value, args: &[String]) -> Option<Result<Value, String>>;
+}
+
+/// No extra filters beyond the built-ins.
+impl FilterHost for () {
+    fn call(&self, _name: &str, _input: &Value, _args: &[String]) -> Option<Result<Value, String>> { None }
+}
+
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Path(String),
+    Literal(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Cond {
+    negate: bool,
+    lhs: Operand,
+    /// `==`/`!=` comparison; `None` tests truthiness
+    cmp: Option<(bool, Operand)>,
+}
+
+/// Maps byte offsets to line/column for error reporting.
+struct LineIndex {
+    starts: Vec<usize>,
+    /// Line number of `src`'s first line in the whole file (templates may start after front matter)
+    first_line: usize,
+}
+
+impl LineIndex {
+    fn new(src: &str, first_line: usize) -> Self {
+        let mut starts = vec![0];
+        starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        Self { starts, first_line }
+    }
+
+    fn pos(&self, src: &str, offset: usize) -> Pos {
+        let line = self.starts.partition_point(|&s| s <= offset);
+        let start = self.starts[line - 1];
+        Pos { line: line - 1 + self.first_line, col: src[start..offset].chars().count() + 1 }
+    }
+}
+
+fn syntax_error(pos: Pos, msg: impl Into<String>) -> MkError {
+    MkError::Syntax { line: pos.line, col: pos.col, msg: msg.into() }
+}
+
+/// Keys understood in `{key}` form; any other `{ident}` is left as text (config bodies are often code).
+const BRACE_KEYS: &[&str] = &["date", "time", "datetime", "year", "author", "file_name", "file_stem"];
+
+/// Length of a `{key}` token starting at `rest` (which begins with `{`), if it names one of [`BRACE_KEYS`].
+fn brace_token_len(rest: &[u8]) -> Option<usize> {
+    let len = rest[1..].iter().take_while(|b| b.is_ascii_alphanumeric() || **b == b'_').count();
+    let key = &rest[1..1 + len];
+    (rest.get(1 + len) == Some(&b'}') && BRACE_KEYS.iter().any(|k| k.as_bytes() == key)).then_some(len + 2)
+}
+
+fn valid_path(s: &str) -> bool {
+    !s.is_empty() && !s.chars().any(char::is_whitespace) && !s.split('.').any(str::is_empty)
+}
+
+/// Split on `sep` outside of double-quoted strings.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut in_quote, mut escaped) = (0, false, false);
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quote => escaped = true,
+            '"' => in_quote = !in_quote,
+            c if c == sep && !in_quote => { parts.push(&s[start..i]); start = i + 1; }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_filter_arg(arg: &str, pos: Pos) -> Result<String, MkError> {
+    let arg = arg.trim();
+    let Some(quoted) = arg.strip_prefix('"') else {
+        if arg.is_empty() { return Err(syntax_error(pos, "empty filter argument")); }
+        return Ok(arg.to_string());
+    };
+    let Some(quoted) = quoted.strip_suffix('"') else { return Err(syntax_error(pos, format!("unterminated string `{arg}`"))); };
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' { out.push(c); continue; }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
+}
+
+/// Parse `PATH|filter|filter:arg,"arg"` from the inside of a token.
+fn parse_var(inner: &str, raw: &str, pos: Pos) -> Result<Var, MkError> {
+    let mut parts = split_unquoted(inner, '|').into_iter();
+    let name = parts.next().unwrap_or_default().trim();
+    if !valid_path(name) {
+        return Err(syntax_error(pos, format!("invalid placeholder name `{name}`")));
+    }
+    let mut filters = Vec::new();
+    for part in parts {
+        let (fname, args) = part.split_once(':').unwrap_or((part, ""));
+        let fname = fname.trim();
+        if fname.is_empty() || !fname.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(syntax_error(pos, format!("invalid filter `{}`", part.trim())));
+        }
+        let args = if args.trim().is_empty() { vec![] } else {
+            split_unquoted(args, ',').into_iter().map(|a| parse_filter_arg(a, pos)).collect::<Result<_, _>>()?
+        };
+        filters.push(Filter { name: fname.to_string(), args });
+    }
+    Ok(Var { name: name.to_string(), filters, raw: raw.to_string(), pos, origin: None })
+}
+
+/// Parse `NAME "Question" default:"x" choices:"a","b"` (everything after the name is optional).
+fn parse_prompt(rest: &str, pos: Pos) -> Result<PromptSpec, MkError> {
+    let mut parts = split_unquoted(rest.trim(), ' ').into_iter().filter(|p| !p.trim().is_empty());
+    let name = parts.next().unwrap_or_default().trim();
+    if !valid_path(name) || name.contains('.') {
+        return Err(syntax_error(pos, format!("expected `#prompt NAME`, got `{}`", rest.trim())));
+    }
+    let mut spec = PromptSpec { name: name.to_string(), ..Default::default() };
+    for part in parts {
+        if part.starts_with('"') {
+            spec.text = Some(parse_filter_arg(part, pos)?);
+            continue;
+        }
+        let Some((key, args)) = part.split_once(':') else {
+            return Err(syntax_error(pos, format!("unexpected `{part}` in `#prompt`")));
+        };
+        let args = split_unquoted(args, ',').into_iter().map(|a| parse_filter_arg(a, pos)).collect::<Result<Vec<_>, _>>()?;
+        match key {
+            "default" => spec.default = args.into_iter().next(),
+            "choices" => spec.choices = args,
+            _ => return Err(syntax_error(pos, format!("unknown `#prompt` option `{key}` (expected default or choices)"))),
+        }
+    }
+    if let (Some(d), false) = (&spec.default, spec.choices.is_empty()) {
+        if !spec.choices.contains(d) {
+            return Err(syntax_error(pos, format!("default `{d}` is not one of the choices")));
+        }
+    }
+    Ok(spec)
+}
+
+fn parse_operand(s: &str, pos: Pos) -> Result<Operand, MkError> {
+    let s = s.trim();
+    if let Some(lit) = s.strip_prefix('"') {
+        let Some(lit) = lit.strip_suffix('"') else { return Err(syntax_error(pos, format!("unterminated string `{s}`"))); };
+        return Ok(Operand::Literal(lit.to_string()));
+    }
+    if !valid_path(s) { return Err(syntax_error(pos, format!("invalid variable name `{s}`"))); }
+    Ok(Operand::Path(s.to_string()))
+}
+
+fn parse_cond(expr: &str, pos: Pos) -> Result<Cond, MkError> {
+    let mut expr = expr.trim();
+    if expr.is_empty() { return Err(syntax_error(pos, "missing condition")); }
+    let mut negate = false;
+    if let Some(rest) = expr.strip_prefix('!').or_else(|| expr.strip_prefix("not ")) {
+        negate = true;
+        expr = rest;
+    }
+    let (lhs, cmp) = match split_comparison(expr) {
+        Some((l, eq, r)) => (l, Some((eq, parse_operand(r, pos)?))),
+        None => (expr, None),
+    };
+    Ok(Cond { negate, lhs: parse_operand(lhs, pos)?, cmp })
+}
+
+/// Split `lhs == rhs` / `lhs != rhs` at the first operator outside of a string literal.
+fn split_comparison(expr: &str) -> Option<(&str, bool, &str)> {
+    let (mut in_quote, mut escaped) = (false, false);
+    for (i, c) in expr.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quote => escaped = true,
+            '"' => in_quote = !in_quote,
+            '=' | '!' if !in_quote && expr[i + 1..].starts_with('=') => return Some((&expr[..i], c == '=', &expr[i + 2..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+enum Block {
+    If { branches: Vec<(Cond, Vec<Node>)>, cond: Option<Cond> },
+    For { item: String, list: String },
+    Named { name: String },
+}
+
+impl Block {
+    fn tag(&self) -> &'static str {
+        match self { Block::If { .. } => "#if", Block::For { .. } => "#for", Block::Named { .. } => "#block" }
+    }
+}
+
+struct Open {
+    pos: Pos,
+    block: Block,
+    body: Vec<Node>,
+}
+
+/// Builds the node tree from tags as they are scanned.
+struct TreeBuilder {
+    root: Vec<Node>,
+    stack: Vec<Open>,
+    block_names: Vec<String>,
+}
+
+impl TreeBuilder {
+    fn current(&mut self) -> &mut Vec<Node> {
+        match self.stack.last_mut() { Some(open) => &mut open.body, None => &mut self.root }
+    }
+
+    fn directive(&mut self, inner: &str, pos: Pos) -> Result<(), MkError> {
+        let (word, rest) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+        match word {
+            "#if" => {
+                let cond = parse_cond(rest, pos)?;
+                self.stack.push(Open { pos, block: Block::If { branches: vec![], cond: Some(cond) }, body: vec![] });
+            }
+            "#elif" | "#else" => {
+                let Some(Open { block: Block::If { branches, cond }, body, .. }) = self.stack.last_mut() else {
+                    return Err(syntax_error(pos, format!("`{word}` outside of `#if`")));
+                };
+                let Some(prev) = cond.take() else {
+                    return Err(syntax_error(pos, format!("`{word}` after `#else`")));
+                };
+                branches.push((prev, std::mem::take(body)));
+                if word == "#elif" { *cond = Some(parse_cond(rest, pos)?); }
+            }
+            "#prompt" => {
+                let spec = parse_prompt(rest, pos)?;
+                self.current().push(Node::Prompt(spec));
+            }
+            "#for" => {
+                let mut parts = rest.split_whitespace();
+                let (Some(item), Some("in"), Some(list), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+                    return Err(syntax_error(pos, "expected `#for ITEM in LIST`"));
+                };
+                if !valid_path(list) || item.contains('.') {
+                    return Err(syntax_error(pos, format!("invalid loop `{}`", rest.trim())));
+                }
+                self.stack.push(Open { pos, block: Block::For { item: item.to_string(), list: list.to_string() }, body: vec![] });
+            }
+            "#extends" => {
+                let parent = rest.trim().trim_matches('"');
+                if parent.is_empty() { return Err(syntax_error(pos, "expected `#extends \"NAME\"`")); }
+                if !self.stack.is_empty() { return Err(syntax_error(pos, "`#extends` must be at the top level")); }
+                if extends(&self.root).is_some() { return Err(syntax_error(pos, "more than one `#extends`")); }
+                self.root.push(Node::Extends { parent: parent.to_string(), pos });
+            }
+            "#block" => {
+                let name = rest.trim();
+                if !valid_path(name) || name.contains('.') { return Err(syntax_error(pos, format!("invalid block name `{name}`"))); }
+                if self.block_names.iter().any(|n| n == name) { return Err(syntax_error(pos, format!("block `{name}` defined twice"))); }
+                self.block_names.push(name.to_string());
+                self.stack.push(Open { pos, block: Block::Named { name: name.to_string() }, body: vec![] });
+            }
+            "/if" | "/for" | "/block" => {
+                let Some(open) = self.stack.pop() else {
+                    return Err(syntax_error(pos, format!("`{word}` without matching `#{}`", &word[1..])));
+                };
+                if open.block.tag()[1..] != word[1..] {
+                    return Err(syntax_error(pos, format!("`{word}` closes `{}` opened at {}:{}", open.block.tag(), open.pos.line, open.pos.col)));
+                }
+                let node = match open.block {
+                    Block::If { mut branches, cond } => match cond {
+                        Some(c) => { branches.push((c, open.body)); Node::If { branches, otherwise: vec![] } }
+                        None => Node::If { branches, otherwise: open.body },
+                    },
+                    Block::For { item, list } => Node::For { item, list, body: open.body },
+                    Block::Named { name } => Node::Block { name, body: open.body },
+                };
+                self.current().push(node);
+            }
+            _ => return Err(syntax_error(pos, format!("unknown directive `{word}`"))),
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<Node>, MkError> {
+        if let Some(open) = self.stack.pop() {
+            return Err(syntax_error(open.pos, format!("unclosed `{}`", open.block.tag())));
+        }
+        Ok(self.root)
+    }
+}
+
+/// If the tag spanning `start..end` is alone on its line, return the range to drop instead
+/// (from the start of the line up to and including the newline).
+fn standalone_span(src: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+    if !src[line_start..start].chars().all(|c| c == ' ' || c == '\t') { return None; }
+    let rest = &src[end..];
+    let line_end = rest.find('\n').map_or(src.len(), |i| end + i + 1);
+    if !src[end..line_end].trim().is_empty() { return None; }
+    Some((line_start, line_end))
+}
+
+/// Tokenize `src` into a node tree. Malformed `<{&...&}>` tokens and unbalanced blocks are
+/// reported with their position; a lone `{` is plain text since braces are common in source code.
+///
+/// `first_line` is the line `src` starts on in its file (templates may start after front matter).
+pub fn parse(src: &str, syntax: Syntax, first_line: usize) -> Result<Vec<Node>, MkError> {
+    let index = LineIndex::new(src, first_line);
+    let bytes = src.as_bytes();
+    let mut tree = TreeBuilder { root: Vec::new(), stack: Vec::new(), block_names: Vec::new() };
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if syntax.angle && bytes[i..].starts_with(ANGLE_OPEN.as_bytes()) {
+            let pos = index.pos(src, i);
+            let body_start = i + ANGLE_OPEN.len();
+            let Some(len) = src[body_start..].find(ANGLE_CLOSE) else {
+                return Err(syntax_error(pos, format!("unterminated `{ANGLE_OPEN}` (missing `{ANGLE_CLOSE}`)")));
+            };
+            let inner = &src[body_start..body_start + len];
+            if let Some(nested) = inner.find(ANGLE_OPEN) {
+                return Err(syntax_error(index.pos(src, body_start + nested), format!("`{ANGLE_OPEN}` inside another token")));
+            }
+            let inner = inner.trim();
+            if inner.is_empty() {
+                return Err(syntax_error(pos, "empty placeholder"));
+            }
+            let end = body_start + len + ANGLE_CLOSE.len();
+            if inner.starts_with(['#', '/', '>']) {
+                let (text_end, resume) = standalone_span(src, i, end).unwrap_or((i, end));
+                if text_start < text_end { tree.current().push(Node::Text(src[text_start..text_end].to_string())); }
+                match inner.strip_prefix('>').map(str::trim) {
+                    Some("") => return Err(syntax_error(pos, "expected a partial name after `>`")),
+                    Some(name) => tree.current().push(Node::Include { name: name.to_string(), pos }),
+                    None => tree.directive(inner, pos)?,
+                }
+                i = resume;
+            } else {
+                let var = parse_var(inner, &src[i..end], pos)?;
+                if text_start < i { tree.current().push(Node::Text(src[text_start..i].to_string())); }
+                tree.current().push(Node::Var(var));
+                i = end;
+            }
+            text_start = i;
+            continue;
+        }
+        if syntax.brace && bytes[i] == b'{' {
+            if let Some(len) = brace_token_len(&bytes[i..]) {
+                if text_start < i { tree.current().push(Node::Text(src[text_start..i].to_string())); }
+                let raw = &src[i..i + len];
+                let var = Var { name: raw[1..len - 1].to_string(), filters: vec![], raw: raw.to_string(), pos: index.pos(src, i), origin: None };
+                tree.current().push(Node::Var(var));
+                i += len;
+                text_start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if text_start < src.len() { tree.current().push(Node::Text(src[text_start..].to_string())); }
+    tree.finish()
+}
+
+/// A token left in the output because its variable is not defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    /// Variable path, e.g. `NAME`
+    pub name: String,
+    pub token: String,
+    pub pos: Pos,
+    /// Where the token came from when it is not the rendered template itself
+    pub origin: Option<String>,
+}
+
+/// Rendered text plus every token that could not be resolved.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub text: String,
+    pub unresolved: Vec<Unresolved>,
+}
+
+/// Variable lookup: loop locals shadow the template's variables.
+struct Scope<'a> {
+    vars: &'a Vars,
+    host: &'a dyn FilterHost,
+    locals: Vec<(String, Value)>,
+    unresolved: Vec<Unresolved>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, path: &str) -> Option<&Value> {
+        let mut segments = path.split('.');
+        let head = segments.next()?;
+        let mut value = self.locals.iter().rev().find(|(k, _)| k == head).map(|(_, v)| v).or_else(|| self.vars.get(head))?;
+        for seg in segments {
+            value = match value { Value::Map(m) => m.get(seg)?, _ => return None };
+        }
+        Some(value)
+    }
+
+    fn operand(&self, op: &Operand) -> Option<Value> {
+        match op {
+            Operand::Path(p) => self.lookup(p).cloned(),
+            Operand::Literal(s) => Some(Value::Str(s.clone())),
+        }
+    }
+
+    fn test(&self, cond: &Cond) -> bool {
+        let lhs = self.operand(&cond.lhs);
+        let result = match &cond.cmp {
+            None => lhs.is_some_and(|v| v.is_truthy()),
+            Some((eq, rhs)) => {
+                let same = lhs.map(|v| v.to_text()) == self.operand(rhs).map(|v| v.to_text());
+                same == *eq
+            }
+        };
+        result != cond.negate
+    }
+
+    /// Look up a variable and run its filter chain. `None` means unresolved.
+    fn eval(&self, var: &Var) -> Result<Option<Value>, MkError> {
+        let mut value = self.lookup(&var.name).cloned();
+        for f in &var.filters {
+            if f.name == "default" {
+                if value.as_ref().is_none_or(|v| v.to_text().is_empty()) {
+                    value = Some(Value::Str(f.args.first().cloned().unwrap_or_default()));
+                }
+                continue;
+            }
+            let Some(input) = &value else { continue };
+            let Some(result) = filters::apply_builtin(&f.name, input, &f.args).or_else(|| self.host.call(&f.name, input, &f.args)) else {
+                return Err(syntax_error(var.pos, format!("unknown filter `{}` (built-in: {})", f.name, filters::BUILTIN.join(", "))));
+            };
+            value = Some(result.map_err(|msg| syntax_error(var.pos, format!("filter `{}`: {msg}", f.name)))?);
+        }
+        Ok(value)
+    }
+
+    fn render_into(&mut self, nodes: &[Node], out: &mut String) -> Result<(), MkError> {
+        for node in nodes {
+            match node {
+                Node::Text(t) => out.push_str(t),
+                Node::Prompt(_) | Node::Extends { .. } | Node::Include { .. } => {}
+                Node::Block { body, .. } => self.render_into(body, out)?,
+                Node::Var(v) => match self.eval(v)? {
+                    Some(value) => out.push_str(&value.to_text()),
+                    None => {
+                        out.push_str(&v.raw);
+                        let missing = Unresolved { name: v.name.clone(), token: v.raw.clone(), pos: v.pos, origin: v.origin.clone() };
+                        if !self.unresolved.contains(&missing) { self.unresolved.push(missing); }
+                    }
+                },
+                Node::If { branches, otherwise } => {
+                    let body = branches.iter().find(|(c, _)| self.test(c)).map_or(otherwise, |(_, b)| b);
+                    self.render_into(body, out)?;
+                }
+                Node::For { item, list, body } => {
+                    let items = match self.lookup(list) {
+                        Some(Value::List(l)) => l.clone(),
+                        Some(v) if v.is_truthy() => vec![v.clone()],
+                        _ => vec![],
+                    };
+                    let count = items.len();
+                    for (n, value) in items.into_iter().enumerate() {
+                        let meta = BTreeMap::from([
+                            ("index".to_string(), Value::Str((n + 1).to_string())),
+                            ("first".to_string(), Value::Str((n == 0).to_string())),
+                            ("last".to_string(), Value::Str((n + 1 == count).to_string())),
+                        ]);
+                        self.locals.push(("loop".to_string(), Value::Map(meta)));
+                        self.locals.push((item.clone(), value));
+                        self.render_into(body, out)?;
+                        self.locals.truncate(self.locals.len() - 2);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// All `#prompt` declarations in the tree, in template order.
+pub fn prompts(nodes: &[Node]) -> Vec<PromptSpec> {
+    let mut out = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Prompt(spec) => out.push(spec.clone()),
+            Node::If { branches, otherwise } => {
+                for (_, body) in branches { out.extend(prompts(body)); }
+                out.extend(prompts(otherwise));
+            }
+            Node::For { body, .. } | Node::Block { body, .. } => out.extend(prompts(body)),
+            Node::Text(_) | Node::Var(_) | Node::Extends { .. } | Node::Include { .. } => {}
+        }
+    }
+    out
+}
+
+/// The parent named by a top-level `#extends`, if any.
+pub fn extends(nodes: &[Node]) -> Option<(&str, Pos)> {
+    nodes.iter().find_map(|n| match n { Node::Extends { parent, pos } => Some((parent.as_str(), *pos)), _ => None })
+}
+
+/// Every `#block` in the tree, by name.
+pub fn blocks(nodes: &[Node]) -> BTreeMap<String, Vec<Node>> {
+    let mut out = BTreeMap::new();
+    for node in nodes {
+        match node {
+            Node::Block { name, body } => {
+                out.insert(name.clone(), body.clone());
+                out.extend(blocks(body));
+            }
+            Node::If { branches, otherwise } => {
+                for (_, body) in branches { out.extend(blocks(body)); }
+                out.extend(blocks(otherwise));
+            }
+            Node::For { body, .. } => out.extend(blocks(body)),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Replace the body of every `#block` that has an entry in `overrides`.
+pub fn apply_blocks(nodes: Vec<Node>, overrides: &BTreeMap<String, Vec<Node>>) -> Vec<Node> {
+    nodes.into_iter().map(|node| match node {
+        Node::Block { name, body } => {
+            let body = match overrides.get(&name) { Some(b) => b.clone(), None => apply_blocks(body, overrides) };
+            Node::Block { name, body }
+        }
+        Node::If { branches, otherwise } => Node::If {
+            branches: branches.into_iter().map(|(c, b)| (c, apply_blocks(b, overrides))).collect(),
+            otherwise: apply_blocks(otherwise, overrides),
+        },
+        Node::For { item, list, body } => Node::For { item, list, body: apply_blocks(body, overrides) },
+        other => other,
+    }).collect()
+}
+
+/// Replace every `>NAME` include with the nodes `load` returns for it, at any depth.
+pub fn expand_includes<E, F>(nodes: Vec<Node>, load: &mut F) -> Result<Vec<Node>, E>
+where
+    F: FnMut(&str, Pos) -> Result<Vec<Node>, E>,
+{
+    let mut out = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::Include { name, pos } => out.extend(load(&name, pos)?),
+            Node::Block { name, body } => out.push(Node::Block { name, body: expand_includes(body, load)? }),
+            Node::If { branches, otherwise } => out.push(Node::If {
+                branches: branches.into_iter().map(|(c, b)| Ok((c, expand_includes(b, load)?))).collect::<Result<_, E>>()?,
+                otherwise: expand_includes(otherwise, load)?,
+            }),
+            Node::For { item, list, body } => out.push(Node::For { item, list, body: expand_includes(body, load)? }),
+            other => out.push(other),
+        }
+    }
+    Ok(out)
+}
+
+/// Record `origin` on every variable that does not have one yet, so tokens inlined from a partial or
+/// a parent template are reported against that file rather than the template being rendered.
+pub fn set_origin(nodes: &mut [Node], origin: &str) {
+    for node in nodes {
+        match node {
+            Node::Var(v) => { v.origin.get_or_insert_with(|| origin.to_string()); }
+            Node::If { branches, otherwise } => {
+                for (_, body) in branches { set_origin(body, origin); }
+                set_origin(otherwise, origin);
+            }
+            Node::For { body, .. } | Node::Block { body, .. } => set_origin(body, origin),
+            Node::Text(_) | Node::Prompt(_) | Node::Extends { .. } | Node::Include { .. } => {}
+        }
+    }
+}
+
+/// Evaluate a standalone `#if`-style condition, e.g. `with_tests` or `lang == "rust"`.
+pub fn eval_condition(expr: &str, vars: &Vars) -> Result<bool, MkError> {
+    let cond = parse_cond(expr, Pos { line: 1, col: 1 })?;
+    let scope = Scope { vars, host: &(), locals: Vec::new(), unresolved: Vec::new() };
+    Ok(scope.test(&cond))
+}
+
+/// Render parsed nodes. Unknown variables are left as their original token text and reported
+/// in [`Output::unresolved`]; a failing or unknown filter is an error.
+pub fn render(nodes: &[Node], vars: &Vars, host: &dyn FilterHost) -> Result<Output, MkError> {
+    let mut text = String::new();
+    let mut scope = Scope { vars, host, locals: Vec::new(), unresolved: Vec::new() };
+    scope.render_into(nodes, &mut text)?;
+    Ok(Output { text, unresolved: scope.unresolved })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_str(src: &str, vars: &[(&str, &str)]) -> String {
+        let vars: Vars = vars.iter().map(|(k, v)| (k.to_string(), Value::from(*v))).collect();
+        render(&parse(src, Syntax::BOTH, 1).unwrap(), &vars, &()).unwrap().text
+    }
+
+    fn error_at(src: &str, first_line: usize) -> (usize, usize, String) {
+        match parse(src, Syntax::ANGLE, first_line) {
+            Err(MkError::Syntax { line, col, msg }) => (line, col, msg),
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn positions_count_from_first_line() {
+        let split = crate::frontmatter::split("+++\nmode = \"644\"\n+++\na\nb <{&bad name&}>\n").unwrap();
+        assert!(split.meta.is_some(), "front matter was not recognised");
+        let (line, col, _) = error_at(split.body, split.body_line);
+        assert_eq!((line, col), (split.body_line + 1, 3));
+        assert_eq!(line, 5);
+        let nodes = parse("x\n  <{&NAME&}>", Syntax::ANGLE, 3).unwrap();
+        let Node::Var(var) = &nodes[1] else { panic!("expected a variable, got {:?}", nodes[1]) };
+        assert_eq!(var.pos, Pos { line: 4, col: 3 });
+    }
+
+    #[test]
+    fn standalone_tags_swallow_their_line() {
+        let src = "a\n  <{&#if X&}>  \nb\n<{&/if&}>\nc\n";
+        assert_eq!(render_str(src, &[("X", "1")]), "a\nb\nc\n");
+        assert_eq!(render_str(src, &[]), "a\nc\n");
+        assert_eq!(render_str("a <{&#if X&}>b<{&/if&}> c\n", &[("X", "1")]), "a b c\n");
+    }
+
+    #[test]
+    fn elif_and_else_misuse_is_reported() {
+        assert!(error_at("<{&#else&}>", 1).2.contains("`#else` outside of `#if`"));
+        assert!(error_at("<{&#for x in L&}><{&#elif Y&}><{&/for&}>", 1).2.contains("`#elif` outside of `#if`"));
+        let (line, _, msg) = error_at("<{&#if X&}>\n<{&#else&}>\n<{&#elif Y&}>\n<{&/if&}>", 1);
+        assert_eq!(line, 3);
+        assert!(msg.contains("`#elif` after `#else`"));
+        assert!(error_at("<{&#if X&}>\n<{&#else&}>\n<{&#else&}>\n<{&/if&}>", 1).2.contains("`#else` after `#else`"));
+    }
+
+    #[test]
+    fn filter_arguments_are_unquoted() {
+        let nodes = parse(r#"<{&N|default:"a, b|c"|replace:x,"\"y\""&}>"#, Syntax::ANGLE, 1).unwrap();
+        let Node::Var(var) = &nodes[0] else { panic!("expected a variable, got {:?}", nodes[0]) };
+        let args: Vec<_> = var.filters.iter().map(|f| (f.name.as_str(), f.args.clone())).collect();
+        assert_eq!(args, [("default", vec!["a, b|c".to_string()]), ("replace", vec!["x".to_string(), "\"y\"".to_string()])]);
+        assert!(error_at(r#"<{&N|default:"open&}>"#, 1).2.contains("unterminated string"));
+    }
+
+    #[test]
+    fn comparison_operators_inside_strings() {
+        let vars: Vars = [("X".to_string(), Value::from("a==b"))].into();
+        assert!(eval_condition(r#"X == "a==b""#, &vars).unwrap());
+        assert!(!eval_condition(r#"X != "a==b""#, &vars).unwrap());
+        assert!(eval_condition(r#"X != "a!=b""#, &vars).unwrap());
+        assert!(eval_condition(r#"! X == "c""#, &vars).unwrap());
+    }
+
+    #[test]
+    fn unresolved_tokens_keep_their_origin() {
+        let mut partial = parse("<{&#if X&}>\n<{&A&}>\n<{&/if&}><{&B&}>", Syntax::ANGLE, 1).unwrap();
+        set_origin(&mut partial, "partials/inner");
+        set_origin(&mut partial, "partials/outer");
+        let mut nodes = parse("<{&C&}>\n", Syntax::ANGLE, 1).unwrap();
+        nodes.extend(partial);
+        let vars: Vars = [("X".to_string(), Value::from("1"))].into();
+        let out = render(&nodes, &vars, &()).unwrap();
+        let found: Vec<_> = out.unresolved.iter().map(|u| (u.name.as_str(), u.origin.as_deref(), u.pos.line)).collect();
+        assert_eq!(found, [("C", None, 1), ("A", Some("partials/inner"), 2), ("B", Some("partials/inner"), 3)]);
+    }
+
+    #[test]
+    fn only_builtin_keys_use_braces() {
+        assert_eq!(render_str("fn {file_stem}() { {other} }", &[("file_stem", "x"), ("other", "y")]), "fn x() { {other} }");
+    }
+}
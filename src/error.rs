@@ -3,6 +3,17 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum MkError {
-    #[error("template not found: {0}")]
-    TemplateNotFound(String),
+    #[error("template not found: {0}{}", did_you_mean(.1))]
+    TemplateNotFound(String, Vec<String>),
+    #[error("{line}:{col}: {msg}")]
+    Syntax { line: usize, col: usize, msg: String },
+    #[error("unresolved placeholders (nothing was written):\n{0}")]
+    Unresolved(String),
+    #[error("template variable `{0}` is not defined and stdin is not a terminal; pass it with -D {0}=VALUE or --vars-file")]
+    NoTerminal(String),
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() { return String::new(); }
+    format!(" (did you mean {}?)", suggestions.iter().map(|s| format!("`{s}`")).collect::<Vec<_>>().join(", "))
 }
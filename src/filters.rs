@@ -0,0 +1,138 @@
+// path: crates/mk/src/filters.rs
+//! Built-in value filters for `<{&KEY|filter:arg&}>` tokens.
+use crate::engine::Value;
+
+/// Names accepted by [`apply_builtin`], for error messages and docs.
+pub const BUILTIN: &[&str] = &[
+    "upper", "lower", "trim", "pascal", "camel", "snake", "kebab", "screaming",
+    "replace", "join", "json_escape", "shell_escape", "xml_escape", "default",
+];
+
+/// Apply a built-in filter. Returns `None` if `name` is not built in.
+/// `default` is handled by the engine since it also applies to missing values.
+pub fn apply_builtin(name: &str, input: &Value, args: &[String]) -> Option<Result<Value, String>> {
+    let text = input.to_text();
+    let out = match name {
+        "upper" => text.to_uppercase(),
+        "lower" => text.to_lowercase(),
+        "trim" => text.trim().to_string(),
+        "pascal" => words(&text).iter().map(|w| capitalize(w)).collect(),
+        "camel" => words(&text).iter().enumerate()
+        .map(|(i, w)| if i == 0 { w.to_lowercase() } else { capitalize(w) })
+        .collect(),
+        "snake" => join_words(&text, "_", false),
+        "kebab" => join_words(&text, "-", false),
+        "screaming" => join_words(&text, "_", true),
+        "replace" => {
+            let [from, to] = args else { return Some(Err("`replace` takes two arguments: replace:\"from\",\"to\"".into())) };
+            text.replace(from.as_str(), to)
+        }
+        "join" => {
+            let sep = args.first().map_or(", ", String::as_str);
+            match input {
+                Value::List(items) => items.iter().map(Value::to_text).collect::<Vec<_>>().join(sep),
+                _ => text,
+            }
+        }
+        "json_escape" => {
+            let quoted = serde_json::to_string(&text).unwrap_or_default();
+            quoted[1..quoted.len() - 1].to_string()
+        }
+        "shell_escape" => shell_escape(&text),
+        "xml_escape" => xml_escape(&text),
+        _ => return None,
+    };
+    Some(Ok(Value::Str(out)))
+}
+
+/// Split an identifier-ish string into words on separators and case boundaries
+/// (`userProfile`, `user_profile`, `User Profile` and `HTTPServer` all split as expected).
+fn words(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !cur.is_empty() { out.push(std::mem::take(&mut cur)); }
+            continue;
+        }
+        if c.is_uppercase() && !cur.is_empty() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower) {
+                out.push(std::mem::take(&mut cur));
+            }
+        }
+        cur.push(c);
+    }
+    if !cur.is_empty() { out.push(cur); }
+    out
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+fn join_words(s: &str, sep: &str, upper: bool) -> String {
+    words(s).iter()
+    .map(|w| if upper { w.to_uppercase() } else { w.to_lowercase() })
+    .collect::<Vec<_>>()
+    .join(sep)
+}
+
+fn shell_escape(s: &str) -> String {
+    let safe = !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if safe { s.to_string() } else { format!("'{}'", s.replace('\'', r"'\''")) }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name: &str, input: &str) -> String {
+        match apply_builtin(name, &Value::from(input), &[]) {
+            Some(Ok(v)) => v.to_text(),
+            other => panic!("`{name}` on `{input}` gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn words_split_on_case_and_separators() {
+        assert_eq!(words("HTTPServer"), ["HTTP", "Server"]);
+        assert_eq!(words("user_profile"), ["user", "profile"]);
+        assert_eq!(words("userProfile"), ["user", "Profile"]);
+        assert_eq!(words("User Profile-card"), ["User", "Profile", "card"]);
+        assert_eq!(words("v2Api"), ["v2", "Api"]);
+        assert!(words("__").is_empty());
+    }
+
+    #[test]
+    fn case_filters() {
+        assert_eq!(apply("pascal", "HTTPServer"), "HttpServer");
+        assert_eq!(apply("snake", "HTTPServer"), "http_server");
+        assert_eq!(apply("pascal", "user_profile"), "UserProfile");
+        assert_eq!(apply("snake", "user_profile"), "user_profile");
+        assert_eq!(apply("camel", "user_profile"), "userProfile");
+        assert_eq!(apply("kebab", "userProfile"), "user-profile");
+        assert_eq!(apply("screaming", "userProfile"), "USER_PROFILE");
+    }
+}
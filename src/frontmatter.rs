@@ -0,0 +1,215 @@
+// path: crates/mk/src/frontmatter.rs
+//! Optional TOML metadata at the top of a file template, between `+++` lines:
+//!
+//! ```text
+//! +++
+//! description = "React function component"
+//! extensions = ["tsx"]
+//! globs = ["*.component.tsx"]
+//! patterns = ["Dockerfile", "re:^Makefile(\\..+)?$"]
+//! default_for = ["tsx"]
+//! priority = 10
+//! mode = "644"
+//! tags = ["react"]
+//! output = "<{&NAME|pascal&}>/index.tsx"
+//!
+//! [vars.NAME]
+//! type = "string"
+//! prompt = "Component name"
+//! required = true
+//! +++
+//! ```
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::{engine::{PromptSpec, Value, Vars}, placeholder};
+
+const FENCE: &str = "+++";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrontMatter {
+    pub description: Option<String>,
+    /// Extensions this template applies to (without the dot); replaces the template's own extension
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// File name globs this template applies to, e.g. `*.test.ts`
+    #[serde(default)]
+    pub globs: Vec<String>,
+    /// File name patterns: exact names (`Dockerfile`), globs, or regexes (`re:^Makefile(\..+)?$`)
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Extensions or patterns this template is the default for when several templates match
+    #[serde(default)]
+    pub default_for: Vec<String>,
+    /// Among equally specific matches, the highest priority wins (default 0)
+    #[serde(default)]
+    pub priority: i32,
+    /// Octal mode for created files (the CLI `--mode` still wins)
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Output path pattern, relative to the target's directory and kept inside it (supports `<{&KEY&}>`)
+    pub output: Option<String>,
+    #[serde(default)]
+    pub vars: BTreeMap<String, VarSpec>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VarType {
+    #[default]
+    String,
+    Bool,
+    Int,
+    List,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VarSpec {
+    #[serde(default, rename = "type")]
+    pub kind: VarType,
+    pub default: Option<toml::Value>,
+    /// Question shown when the variable has to be asked for
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub choices: Vec<String>,
+    /// Required variables are asked for when missing; optional ones fall back to `default`
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A template split into its metadata and body. `body_line` is the 1-based line the body starts on.
+#[derive(Debug, Clone)]
+pub struct Split<'a> {
+    pub meta: Option<FrontMatter>,
+    pub body: &'a str,
+    pub body_line: usize,
+}
+
+/// Split off a leading `+++` block. Templates without one are returned unchanged.
+pub fn split(src: &str) -> Result<Split<'_>> {
+    let first_end = src.find('\n').map_or(src.len(), |i| i + 1);
+    if src[..first_end].trim_end() != FENCE {
+        return Ok(Split { meta: None, body: src, body_line: 1 });
+    }
+    let mut offset = first_end;
+    let mut line = 2;
+    while offset < src.len() {
+        let end = src[offset..].find('\n').map_or(src.len(), |i| offset + i + 1);
+        if src[offset..end].trim_end() == FENCE {
+            let meta: FrontMatter = toml::from_str(&src[first_end..offset]).context("parsing front matter")?;
+            return Ok(Split { meta: Some(meta), body: &src[end..], body_line: line + 1 });
+        }
+        offset = end;
+        line += 1;
+    }
+    bail!("unterminated front matter (missing closing `{FENCE}`)")
+}
+
+/// Read just the metadata of a template file.
+pub fn read(path: &Path) -> Result<Option<FrontMatter>> {
+    let src = fs::read_to_string(path).with_context(|| format!("reading template {}", path.display()))?;
+    Ok(split(&src).with_context(|| format!("in template {}", path.display()))?.meta)
+}
+
+impl FrontMatter {
+    /// [`apply_var_defaults`] for this template's `vars`.
+    pub fn apply_defaults(&self, vars: &mut Vars) -> Vec<PromptSpec> {
+        apply_var_defaults(&self.vars, vars)
+    }
+
+    /// [`coerce_vars`] for this template's `vars`.
+    pub fn coerce(&self, vars: &mut Vars) -> Result<()> {
+        coerce_vars(&self.vars, vars)
+    }
+}
+
+/// Apply defaults for missing optional variables and return prompts for missing required ones.
+pub fn apply_var_defaults(specs: &BTreeMap<String, VarSpec>, vars: &mut Vars) -> Vec<PromptSpec> {
+    let mut prompts = Vec::new();
+    for (name, spec) in specs {
+        if vars.contains_key(name) { continue; }
+        let default = spec.default.clone().map(toml_to_text);
+        if spec.required {
+            prompts.push(PromptSpec { name: name.clone(), text: spec.prompt.clone(), default, choices: spec.choices.clone() });
+        } else if let Some(d) = spec.default.clone() {
+            vars.insert(name.clone(), placeholder::toml_to_value(d));
+        }
+    }
+    prompts
+}
+
+/// Check declared variables against their types and choices, converting where needed
+/// (a comma-separated string becomes a list, `yes`/`no` become `true`/`false`).
+pub fn coerce_vars(specs: &BTreeMap<String, VarSpec>, vars: &mut Vars) -> Result<()> {
+    for (name, spec) in specs {
+        let Some(value) = vars.get(name).cloned() else { continue };
+        let coerced = match (spec.kind, value) {
+            (VarType::List, Value::Str(s)) => Value::List(s.split(',').map(|p| Value::Str(p.trim().to_string())).filter(|v| !v.to_text().is_empty()).collect()),
+            (VarType::List, v @ Value::List(_)) => v,
+            (VarType::Bool, v) => match v.to_text().trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Value::Str("true".into()),
+                "false" | "no" | "n" | "0" | "" => Value::Str("false".into()),
+                other => bail!("variable `{name}` must be a bool, got `{other}`"),
+            },
+            (VarType::Int, v) => {
+                let text = v.to_text();
+                if text.trim().parse::<i64>().is_err() { bail!("variable `{name}` must be an integer, got `{text}`"); }
+                Value::Str(text.trim().to_string())
+            }
+            (VarType::String, v @ Value::Str(_)) => v,
+            (kind, _) => bail!("variable `{name}` must be a {}", format!("{kind:?}").to_lowercase()),
+        };
+        if spec.kind != VarType::List && !spec.choices.is_empty() && !spec.choices.contains(&coerced.to_text()) {
+            bail!("variable `{name}` must be one of: {}", spec.choices.join(", "));
+        }
+        vars.insert(name.clone(), coerced);
+    }
+    Ok(())
+}
+
+fn toml_to_text(v: toml::Value) -> String {
+    match v {
+        toml::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coerce(spec: &str, value: Value) -> Result<Value> {
+        let specs: BTreeMap<String, VarSpec> = toml::from_str(&format!("[X]\n{spec}")).unwrap();
+        let mut vars: Vars = [("X".to_string(), value)].into();
+        coerce_vars(&specs, &mut vars)?;
+        Ok(vars.remove("X").unwrap())
+    }
+
+    fn text(v: Value) -> String { v.to_text() }
+
+    #[test]
+    fn values_are_coerced_to_their_type() {
+        let list = coerce("type = \"list\"", Value::from("a, b,,c")).unwrap();
+        let Value::List(items) = list else { panic!("expected a list, got {list:?}") };
+        assert_eq!(items.into_iter().map(text).collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(text(coerce("type = \"bool\"", Value::from("Yes")).unwrap()), "true");
+        assert_eq!(text(coerce("type = \"bool\"", Value::from("")).unwrap()), "false");
+        assert!(coerce("type = \"bool\"", Value::from("maybe")).is_err());
+        assert_eq!(text(coerce("type = \"int\"", Value::from(" 42 ")).unwrap()), "42");
+        assert!(coerce("type = \"int\"", Value::from("4.2")).is_err());
+        assert!(coerce("type = \"string\"", Value::List(vec![])).is_err());
+    }
+
+    #[test]
+    fn choices_are_enforced() {
+        let spec = "choices = [\"class\", \"function\"]";
+        assert_eq!(text(coerce(spec, Value::from("class")).unwrap()), "class");
+        let err = coerce(spec, Value::from("struct")).unwrap_err().to_string();
+        assert!(err.contains("must be one of: class, function"), "{err}");
+    }
+}
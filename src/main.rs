@@ -6,6 +6,13 @@ mod ops;
 mod error;
 mod templater;
 mod placeholder;
+mod engine;
+mod filters;
+mod prompt;
+mod frontmatter;
+mod registry;
+mod scaffold;
+mod pattern;
 
 use anyhow::Result;
 
@@ -8,8 +8,6 @@ use std::{
 
 use anyhow::{bail, Context, Result};
 
-use crate::{config::Template, template};
-
 pub fn create_dir<P: AsRef<Path>>(path: P, parents: bool, mode: Option<&str>, dry_run: bool) -> Result<()> {
     let p = path.as_ref();
     if dry_run {
@@ -37,16 +35,15 @@ pub fn create_dir<P: AsRef<Path>>(path: P, parents: bool, mode: Option<&str>, dr
     Ok(())
 }
 
+/// Write already-rendered `content` to `path`. `mode` is the final mode (CLI flag or template mode).
 pub fn create_file(
     path: &Path,
-    tmpl: Option<&Template>,
-    ctx: &template::ContextVars,
+    content: &[u8],
     parents: bool,
     force: bool,
-        no_clobber: bool,
-        mode: Option<&str>,
-        stdin_content: Option<&str>,
-        dry_run: bool,
+    no_clobber: bool,
+    mode: Option<&str>,
+    dry_run: bool,
 ) -> Result<()> {
     if dry_run {
         println!("mk: create file {}", path.display());
@@ -66,14 +63,6 @@ pub fn create_file(
         return Ok(());
     }
 
-    // Decide content
-    let mut content = String::new();
-    if let Some(s) = stdin_content {
-        content.push_str(s);
-    } else if let Some(t) = tmpl {
-        content = crate::template::render(&t.body, ctx);
-    }
-
     // Open with overwrite or create_new semantics
     let f = if force {
         OpenOptions::new()
@@ -86,26 +75,42 @@ pub fn create_file(
         .create_new(!exists)
         .create(true)
         .write(true)
+        .truncate(false)
         .open(path)
     };
     let mut f = f.with_context(|| format!("opening {}", path.display()))?;
 
     if !content.is_empty() {
-        f.write_all(content.as_bytes())
+        f.write_all(content)
         .with_context(|| format!("writing {}", path.display()))?;
     }
 
-    // Apply mode: CLI flag takes precedence over template mode
     #[cfg(unix)]
-    {
-        let mode_final = mode.or_else(|| tmpl.and_then(|t| t.mode.as_deref()));
-        if let Some(m) = mode_final { apply_mode_unix(path, m)?; }
-    }
+    if let Some(m) = mode { apply_mode_unix(path, m)?; }
 
     println!("mk: created {}", path.display());
     Ok(())
 }
 
+/// Append `text` to `path`, creating it if needed.
+pub fn append_file(path: &Path, text: &str, parents: bool) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if parents {
+            fs::create_dir_all(parent)
+            .with_context(|| format!("creating parents for {}", path.display()))?;
+        }
+    }
+    let mut f = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .with_context(|| format!("opening {}", path.display()))?;
+    f.write_all(text.as_bytes())
+    .with_context(|| format!("writing {}", path.display()))?;
+    println!("mk: appended to {}", path.display());
+    Ok(())
+}
+
 #[cfg(unix)]
 fn apply_mode_unix(path: &Path, octal: &str) -> Result<()> {
     use std::os::unix::fs::PermissionsExt;
@@ -0,0 +1,108 @@
+// path: crates/mk/src/pattern.rs
+//! File name patterns for implicit template matching.
+//!
+//! A pattern is an exact name (`Dockerfile`), a glob (`*.test.ts`) or a regex (`re:^Makefile(\..+)?$`).
+//! When several templates match a file, the most specific pattern wins: exact names beat globs, globs
+//! with more literal characters beat shorter ones (`*.test.ts` over `*.ts`), and regexes come last.
+use anyhow::{Context, Result};
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Name(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+/// How specific a match is; greater is more specific.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity {
+    kind: u8,
+    literal: usize,
+}
+
+impl Specificity {
+    /// A template named on the command line with `-t`
+    pub const EXPLICIT: Self = Self { kind: 4, literal: 0 };
+}
+
+impl Pattern {
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Some(re) = s.strip_prefix("re:") {
+            return Ok(Pattern::Regex(Regex::new(re).with_context(|| format!("invalid pattern `{s}`"))?));
+        }
+        Ok(if s.contains(['*', '?']) { Pattern::Glob(s.to_string()) } else { Pattern::Name(s.to_string()) })
+    }
+
+    /// The glob `*.EXT`.
+    pub fn extension(ext: &str) -> Self {
+        Pattern::Glob(format!("*.{ext}"))
+    }
+
+    pub fn matches(&self, file_name: &str) -> bool {
+        match self {
+            Pattern::Name(n) => n == file_name,
+            Pattern::Glob(g) => glob_match(g, file_name),
+            Pattern::Regex(r) => r.is_match(file_name),
+        }
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            Pattern::Name(n) => Specificity { kind: 3, literal: n.chars().count() },
+            Pattern::Glob(g) => Specificity { kind: 2, literal: g.chars().filter(|c| !matches!(c, '*' | '?')).count() },
+            Pattern::Regex(_) => Specificity { kind: 1, literal: 0 },
+        }
+    }
+}
+
+/// Minimal glob: `*` matches any run of characters, `?` a single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        match p.get(pi) {
+            Some('*') => { backtrack = Some((pi, ni)); pi += 1; }
+            Some(&c) if c == '?' || c == n[ni] => { pi += 1; ni += 1; }
+            _ => match backtrack {
+                Some((bp, bn)) => { pi = bp + 1; ni = bn + 1; backtrack = Some((bp, bn + 1)); }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> Specificity { Pattern::parse(s).unwrap().specificity() }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("*.ts", "app.test.ts"));
+        assert!(glob_match("*.test.ts", "app.test.ts"));
+        assert!(!glob_match("*.test.ts", "app.ts"));
+        assert!(glob_match("Makefile.*", "Makefile.am"));
+        assert!(!glob_match("Makefile.*", "Makefile"));
+        assert!(glob_match("?.rs", "a.rs"));
+        assert!(!glob_match("?.rs", "ab.rs"));
+        assert!(glob_match("*a*b*", "xaybz"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a", ""));
+    }
+
+    #[test]
+    fn more_specific_patterns_rank_higher() {
+        assert!(spec("*.test.ts") > spec("*.ts"));
+        assert!(spec("app.test.ts") > spec("*.test.ts"));
+        assert!(spec("Dockerfile") > spec("Dockerfile.*"));
+        assert!(spec("*.ts") > spec("re:^.*\\.test\\.ts$"));
+        assert!(Specificity::EXPLICIT > spec("a-very-long-exact-file-name.txt"));
+        assert!(matches!(Pattern::parse("*.rs").unwrap(), Pattern::Glob(_)));
+        assert!(matches!(Pattern::parse("Dockerfile").unwrap(), Pattern::Name(_)));
+        assert!(Pattern::parse("re:(").is_err());
+    }
+}
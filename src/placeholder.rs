@@ -1,46 +1,203 @@
 // path: crates/mk/src/placeholder.rs
-use std::{collections::HashMap, path::Path, rc::Rc, cell::RefCell};
-use chrono::Local;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use anyhow::{anyhow, bail, Context, Result};
 use dirs::config_dir;
-use mlua::{Lua, Value, Variadic};
-use once_cell::sync::OnceCell;
+use mlua::{Debug, Function, HookTriggers, Lua, Table, Thread, Value, Variadic};
+use once_cell::{sync::OnceCell, unsync};
+use sha2::{Digest, Sha256};
 
-static LUA_PLACEHOLDERS_CACHE: OnceCell<HashMap<String, String>> = OnceCell::new();
+use crate::{
+    config::{Config, LuaConfig, LuaErrors},
+    engine::{self, FilterHost, Vars},
+    prompt,
+    template::ContextVars,
+};
 
-pub fn builtins_for(path: &Path) -> HashMap<String, String> {
-    let mut m = HashMap::new();
-    let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_string();
-    let now = Local::now();
-    m.insert("FILENAME".into(), file_name);
-    m.insert("DATE".into(), now.format("%Y-%m-%d").to_string());
-    m.insert("TIME".into(), now.format("%H:%M:%S").to_string());
-    m.insert("DATETIME".into(), now.format("%Y-%m-%d %H:%M:%S").to_string());
-    m
+static LUA_PLACEHOLDERS_CACHE: OnceCell<Vars> = OnceCell::new();
+static LUA_SETTINGS: OnceCell<(LuaErrors, LuaConfig)> = OnceCell::new();
+
+/// Globals left out of the sandbox unless `lua.allow` grants them. Without `load`, scripts get a
+/// `load` that only accepts source text, since binary chunks (from `string.dump`) bypass the VM's checks.
+const GUARDED: [&str; 7] = ["os", "io", "package", "require", "dofile", "loadfile", "load"];
+
+/// `load` restricted to text chunks; an explicit `env` argument is passed on, even when it is nil.
+const TEXT_LOAD: &str = r#"
+local load = ...
+return function(chunk, name, _, ...)
+  if select('#', ...) > 0 then return load(chunk, name, 't', ...) end
+  return load(chunk, name, 't')
+end
+"#;
+
+thread_local! {
+    /// VM that ran the placeholder script, kept alive so `mk.filters` can be called while rendering
+    static LUA_VM: unsync::OnceCell<(Lua, PathBuf)> = const { unsync::OnceCell::new() };
+    /// Results of `placeholders(ctx)` by target and template, so the function runs once per file and template
+    static PER_TARGET: RefCell<HashMap<(PathBuf, Option<String>), Vars>> = RefCell::new(HashMap::new());
+    /// Start of the current call into Lua and the instructions it has run, for the `[lua]` limits
+    static BUDGET: Cell<(Instant, u64)> = Cell::new((Instant::now(), 0));
+}
+
+/// How often the limit hook runs, in VM instructions.
+const HOOK_EVERY: u32 = 1000;
+
+/// Parse a `-D KEY=VALUE` definition.
+pub fn parse_define(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{s}`")),
+    }
+}
+
+/// Load variables from a `.json` file, or TOML for any other extension.
+/// Arrays become lists and tables become maps, so they can drive `#for` blocks.
+pub fn vars_from_file(path: &Path) -> Result<Vars> {
+    let src = fs::read_to_string(path).with_context(|| format!("reading vars file {}", path.display()))?;
+    let value = if path.extension().is_some_and(|e| e == "json") {
+        let json: serde_json::Value = serde_json::from_str(&src).with_context(|| format!("parsing {}", path.display()))?;
+        json_to_value(json)
+    } else {
+        let toml: toml::Value = toml::from_str(&src).with_context(|| format!("parsing {}", path.display()))?;
+        toml_to_value(toml)
+    };
+    let engine::Value::Map(fields) = value else { bail!("vars file {} must contain a table/object", path.display()) };
+    Ok(fields.into_iter().collect())
+}
+
+pub fn toml_to_value(v: toml::Value) -> engine::Value {
+    match v {
+        toml::Value::String(s) => engine::Value::Str(s),
+        toml::Value::Array(items) => engine::Value::List(items.into_iter().map(toml_to_value).collect()),
+        toml::Value::Table(t) => engine::Value::Map(t.into_iter().map(|(k, v)| (k, toml_to_value(v))).collect()),
+        other => engine::Value::Str(other.to_string()),
+    }
+}
+
+fn json_to_value(v: serde_json::Value) -> engine::Value {
+    match v {
+        serde_json::Value::Null => engine::Value::Str(String::new()),
+        serde_json::Value::String(s) => engine::Value::Str(s),
+        serde_json::Value::Array(items) => engine::Value::List(items.into_iter().map(json_to_value).collect()),
+        serde_json::Value::Object(o) => engine::Value::Map(o.into_iter().map(|(k, v)| (k, json_to_value(v))).collect()),
+        other => engine::Value::Str(other.to_string()),
+    }
+}
+
+/// Apply `lua_errors` and `[lua]` from the config for the rest of the run. Without it, errors are
+/// fatal and the sandbox grants nothing.
+pub fn configure(cfg: &Config) {
+    let _ = LUA_SETTINGS.set((cfg.lua_errors, cfg.lua.clone()));
+}
+
+fn settings() -> (LuaErrors, LuaConfig) {
+    LUA_SETTINGS.get().cloned().unwrap_or_default()
+}
+
+/// Fail with `err`, or print it as a warning when `lua_errors = "warn"`.
+fn lua_error(err: anyhow::Error) -> Result<()> {
+    match settings().0 {
+        LuaErrors::Error => Err(err),
+        LuaErrors::Warn => {
+            eprintln!("mk: warning: {err:#}");
+            Ok(())
+        }
+    }
+}
+
+/// Cached accessor (process-lifetime) for Lua placeholders. A failing script yields no values when
+/// errors are only warnings.
+pub fn lua_placeholders_cached() -> Result<&'static Vars> {
+    LUA_PLACEHOLDERS_CACHE.get_or_try_init(|| lua_placeholders().or_else(|e| lua_error(e).map(|()| Vars::new())))
+}
+
+/// Lua placeholders for one target: the script's table, overridden by what its global
+/// `placeholders(ctx)` function returns, if it defines one. The function runs once per target and
+/// template on the same VM; `ctx` has `path`, `name`, `stem`, `ext`, `dir`, `cwd`, `template` (config
+/// key or file path, if any) and `vars` (the `-D`/`--vars-file` values).
+pub fn lua_placeholders_for(ctx: &ContextVars) -> Result<Vars> {
+    let mut vars = lua_placeholders_cached()?.clone();
+    let key = (ctx.path.clone(), ctx.template.clone());
+    if let Some(cached) = PER_TARGET.with(|c| c.borrow().get(&key).cloned()) {
+        vars.extend(cached);
+        return Ok(vars);
+    }
+    let mut script = PathBuf::new();
+    let computed = LUA_VM.with(|vm| -> mlua::Result<Vars> {
+        let Some((lua, path)) = vm.get() else { return Ok(Vars::new()) };
+        script.clone_from(path);
+        let Value::Function(func) = lua.globals().get::<_, Value>("placeholders")? else { return Ok(Vars::new()) };
+        start_budget();
+        let out: Value = func.call(context_table(lua, ctx)?)?;
+        let Some(engine::Value::Map(fields)) = lua_to_value(&out) else { return Ok(Vars::new()) };
+        Ok(fields.into_iter().collect())
+    });
+    let computed = match computed {
+        Ok(v) => v,
+        Err(e) => {
+            lua_error(anyhow!(describe(&e)).context(format!("Lua error in placeholders() of {} for {}", script.display(), ctx.path.display())))?;
+            Vars::new()
+        }
+    };
+    PER_TARGET.with(|c| c.borrow_mut().insert(key, computed.clone()));
+    vars.extend(computed);
+    Ok(vars)
 }
 
-/// Cached accessor (process-lifetime) for Lua placeholders
-pub fn lua_placeholders_cached() -> &'static HashMap<String, String> {
-    LUA_PLACEHOLDERS_CACHE.get_or_init(|| lua_placeholders())
+/// The `ctx` table passed to `placeholders(ctx)`.
+fn context_table<'lua>(lua: &'lua Lua, ctx: &ContextVars) -> mlua::Result<Table<'lua>> {
+    let t = lua.create_table()?;
+    let path = &ctx.path;
+    t.set("path", path.to_string_lossy().as_ref())?;
+    t.set("name", ctx.file_name.as_str())?;
+    t.set("stem", ctx.file_stem.as_str())?;
+    t.set("ext", path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default())?;
+    t.set("dir", path.parent().map(|d| d.to_string_lossy().to_string()).unwrap_or_default())?;
+    let cwd = std::env::current_dir().map(|d| d.to_string_lossy().to_string()).unwrap_or_default();
+    t.set("cwd", cwd)?;
+    t.set("template", ctx.template.as_deref())?;
+    let vars = lua.create_table()?;
+    for (k, v) in &ctx.vars { vars.set(k.as_str(), value_to_lua(lua, v)?)?; }
+    t.set("vars", vars)?;
+    Ok(t)
 }
 
 /// Execute a Lua script to collect placeholders.
 /// Supports two styles:
-/// 1) Return a table: `return { KEY = "VALUE", ... }`
+/// 1) Return a table: `return { KEY = "VALUE", ... }` (nested tables become lists/maps for `#for`)
 /// 2) Print lines: `KEY=VALUE` (we capture `print(...)` and parse lines)
-/// Search order: ./mk_placeholders.lua, then ~/.config/mk/mk_placeholders.lua
-pub fn lua_placeholders() -> HashMap<String, String> {
-    use std::path::PathBuf;
-    let mut m = HashMap::new();
+///
+/// Values that depend on the file being created come from [`lua_placeholders_for`].
+///
+/// Search order: ./mk_placeholders.lua, then ~/.config/mk/mk_placeholders.lua. A project-local
+/// script only runs once the user trusts it (see [`trusted`]); until then the user's script is used.
+///
+/// The script runs in a sandbox: `os`, `io`, `package`, `require`, `dofile` and `loadfile` are only
+/// there when `lua.allow` grants them, and `load` only accepts source text unless `load` is granted.
+///
+/// The script may also register filters: `mk.filters.name = function(value, ...) return ... end`.
+pub fn lua_placeholders() -> Result<Vars> {
+    let mut m = Vars::new();
     let local = Path::new("./mk_placeholders.lua");
     let home: PathBuf = config_dir()
     .unwrap_or_else(|| Path::new(".").to_path_buf())
     .join("mk")
     .join("mk_placeholders.lua");
 
-    let script_path = if local.exists() { local.to_path_buf() } else if home.exists() { home } else { return m };
+    // An untrusted project script falls back to the user's own
+    let script_path = if local.exists() && trusted(local)? { local.to_path_buf() } else if home.exists() { home } else { return Ok(m) };
 
-    // Spin up Lua VM with a custom print that captures lines
+    // Spin up a sandboxed Lua VM with a custom print that captures lines
     let lua = Lua::new();
+    let lua_cfg = settings().1;
+    sandbox(&lua, &lua_cfg.allow)?;
+    limit(&lua, &lua_cfg)?;
 
     let captured: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
     let out = Rc::clone(&captured);
@@ -60,38 +217,340 @@ pub fn lua_placeholders() -> HashMap<String, String> {
     }) {
         let _ = lua.globals().set("print", print_fn);
     }
+    if let Ok(mk) = lua.create_table() {
+        if let Ok(filters) = lua.create_table() { let _ = mk.set("filters", filters); }
+        if let Ok(hooks) = lua.create_table() { let _ = mk.set("hooks", hooks); }
+        let _ = lua.globals().set("mk", mk);
+    }
 
-    // Load and execute the script
-    match std::fs::read_to_string(&script_path) {
-        Ok(src) => {
-            // set_name expects Into<String>; use &str via .to_string_lossy().as_ref()
-            let name = script_path.to_string_lossy();
-            let chunk = lua.load(&src).set_name(name.as_ref());
-
-            match chunk.eval::<Value>() {
-                Ok(Value::Table(t)) => {
-                    // Preferred: table return
-                    for pair in t.pairs::<String, Value>() {
-                        if let Ok((k, v)) = pair {
-                            if let Some(s) = value_to_string(&v) { m.insert(k, s); }
-                        }
-                    }
-                }
-                Ok(_) | Err(_) => {
-                    // Fallback: parse captured prints as KEY=VALUE lines
-                    for line in captured.borrow().iter() {
-                        if let Some((k, v)) = line.split_once('=') {
-                            let k = k.trim(); let v = v.trim();
-                            if !k.is_empty() { m.insert(k.to_string(), v.to_string()); }
-                        }
-                    }
+    // Load and execute the script; `@path` makes Lua report `path:line:` in messages and tracebacks
+    let src = fs::read_to_string(&script_path).with_context(|| format!("reading Lua script {}", script_path.display()))?;
+    start_budget();
+    let outcome = match lua.load(&src).set_name(format!("@{}", script_path.display())).eval::<Value>() {
+        Ok(Value::Table(t)) => {
+            // Preferred: table return
+            for (k, v) in t.pairs::<String, Value>().flatten() {
+                if let Some(v) = lua_to_value(&v) { m.insert(k, v); }
+            }
+            Ok(())
+        }
+        Ok(_) => {
+            // Fallback: parse captured prints as KEY=VALUE lines
+            for line in captured.borrow().iter() {
+                if let Some((k, v)) = line.split_once('=') {
+                    let k = k.trim(); let v = v.trim();
+                    if !k.is_empty() { m.insert(k.to_string(), v.into()); }
                 }
             }
+            Ok(())
         }
-        Err(_) => { /* ignore */ }
+        Err(e) => Err(anyhow!(describe(&e)).context(format!("Lua error in {}", script_path.display()))),
+    };
+
+    // Later calls (`placeholders`, filters, hooks) print to stderr, which keeps stdout clean for `mk render`
+    let to_stderr = lua.create_function(|_, args: Variadic<Value>| {
+        let parts = args.iter().map(Value::to_string).collect::<mlua::Result<Vec<_>>>()?;
+        eprintln!("{}", parts.join("\t"));
+        Ok(())
+    })?;
+    lua.globals().set("print", to_stderr)?;
+
+    // Kept even after an error, so filters and `placeholders` defined before it still work
+    LUA_VM.with(|vm| { let _ = vm.set((lua, script_path)); });
+    outcome.map(|()| m)
+}
+
+/// What `mk.hooks.pre_create` decided for a target.
+#[derive(Debug, Default)]
+pub struct PreCreate {
+    /// Set when the hook refused the target, with its reason
+    pub veto: Option<String>,
+    pub path: Option<PathBuf>,
+    pub templates: Option<Vec<String>>,
+}
+
+/// Changes `mk.hooks.post_create` asked for after a file was written.
+#[derive(Debug, Default)]
+pub struct PostCreate {
+    /// New content for the file that was just written
+    pub content: Option<String>,
+    /// Files to create or overwrite
+    pub write: Vec<(PathBuf, String)>,
+    /// Text to append to files, e.g. to register a new module in an index
+    pub append: Vec<(PathBuf, String)>,
+}
+
+/// `mk.hooks.pre_create({ path, templates, vars })`, before a target is planned. Returning `false`
+/// (optionally with a reason) refuses it; a table may set `path` and `template` (a name or a list).
+pub fn pre_create(target: &Path, templates: &[String], vars: &Vars) -> Result<PreCreate> {
+    call_hook("pre_create", target, |lua| {
+        let ev = lua.create_table()?;
+        ev.set("path", target.to_string_lossy().as_ref())?;
+        ev.set("templates", lua.create_sequence_from(templates.iter().map(String::as_str))?)?;
+        let t = lua.create_table()?;
+        for (k, v) in vars { t.set(k.as_str(), value_to_lua(lua, v)?)?; }
+        ev.set("vars", t)?;
+        Ok(ev)
+    }, |out| {
+        let mut values = out.into_iter();
+        Ok(match values.next() {
+            Some(Value::Boolean(false)) => {
+                let reason = values.next().and_then(|v| value_to_string(&v)).unwrap_or_else(|| "refused by mk.hooks.pre_create".to_string());
+                PreCreate { veto: Some(reason), ..Default::default() }
+            }
+            Some(Value::Table(t)) => PreCreate {
+                veto: None,
+                path: t.get::<_, Option<String>>("path")?.map(PathBuf::from),
+                templates: match t.get::<_, Value>("template")? {
+                    Value::Nil => None,
+                    v => match lua_to_value(&v) {
+                        Some(engine::Value::List(items)) => Some(items.iter().map(engine::Value::to_text).collect()),
+                        Some(v) => Some(vec![v.to_text()]),
+                        None => None,
+                    },
+                },
+            },
+            _ => PreCreate::default(),
+        })
+    })
+}
+
+/// `mk.hooks.post_create({ path, content })`, after a file was written. A returned table may set
+/// `content`, and `write`/`append` as `{ [path] = text }`.
+pub fn post_create(target: &Path, content: &str) -> Result<PostCreate> {
+    call_hook("post_create", target, |lua| {
+        let ev = lua.create_table()?;
+        ev.set("path", target.to_string_lossy().as_ref())?;
+        ev.set("content", content)?;
+        Ok(ev)
+    }, |out| {
+        let Some(Value::Table(t)) = out.into_iter().next() else { return Ok(PostCreate::default()) };
+        let files = |key: &str| -> mlua::Result<Vec<(PathBuf, String)>> {
+            let Value::Table(map) = t.get::<_, Value>(key)? else { return Ok(vec![]) };
+            map.pairs::<String, String>().map(|kv| kv.map(|(k, v)| (PathBuf::from(k), v))).collect()
+        };
+        Ok(PostCreate { content: t.get("content")?, write: files("write")?, append: files("append")? })
+    })
+}
+
+/// `mk.hooks.on_skip({ path, reason })` when a target is left alone; `reason` is `"exists"` (`-n` or an
+/// overwrite rule), `"declined"` (answered no) or `"refused"` (by `pre_create`).
+pub fn on_skip(target: &Path, reason: &str) -> Result<()> {
+    call_hook("on_skip", target, |lua| {
+        let ev = lua.create_table()?;
+        ev.set("path", target.to_string_lossy().as_ref())?;
+        ev.set("reason", reason)?;
+        Ok(ev)
+    }, |_| Ok(()))
+}
+
+/// Call `mk.hooks.<name>(event)` if the script defines it and turn its results into `T`. Errors follow
+/// `lua_errors`; when they are only warnings the hook counts as having returned nothing.
+fn call_hook<T: Default>(
+    name: &str,
+    target: &Path,
+    event: impl for<'lua> FnOnce(&'lua Lua) -> mlua::Result<Table<'lua>>,
+    read: impl for<'lua> FnOnce(mlua::MultiValue<'lua>) -> mlua::Result<T>,
+) -> Result<T> {
+    lua_placeholders_cached()?;
+    let mut script = PathBuf::new();
+    let result = LUA_VM.with(|vm| -> mlua::Result<T> {
+        let Some((lua, path)) = vm.get() else { return Ok(T::default()) };
+        script.clone_from(path);
+        let hook = lua.globals().get::<_, Table>("mk").and_then(|mk| mk.get::<_, Table>("hooks")).and_then(|h| h.get::<_, Value>(name));
+        let Ok(Value::Function(hook)) = hook else { return Ok(T::default()) };
+        start_budget();
+        read(hook.call(event(lua)?)?)
+    });
+    result.or_else(|e| {
+        lua_error(anyhow!(describe(&e)).context(format!("Lua error in mk.hooks.{name} of {} for {}", script.display(), target.display())))?;
+        Ok(T::default())
+    })
+}
+
+/// Remove the [`GUARDED`] globals from `lua`, then put back what `allow` grants: `lib` or `lib.*` for a
+/// whole library, `lib.func` for one function.
+fn sandbox(lua: &Lua, allow: &[String]) -> Result<()> {
+    let globals = lua.globals();
+    let mut full = Vec::new();
+    for name in GUARDED {
+        full.push((name, globals.get::<_, Value>(name)?));
+        globals.set(name, Value::Nil)?;
+    }
+    let text_load: Function = lua.load(TEXT_LOAD).set_name("=mk").call(full.iter().find(|(n, _)| *n == "load").map(|(_, v)| v.clone()))?;
+    globals.set("load", text_load)?;
+    for cap in allow {
+        let (lib, func) = match cap.split_once('.') {
+            Some((lib, "*")) => (lib, None),
+            Some((lib, func)) => (lib, Some(func)),
+            None => (cap.as_str(), None),
+        };
+        let unknown = || anyhow!("unknown Lua capability `{cap}` in `lua.allow` (expected e.g. `os.date`, `io`, `require`, `load`)");
+        let value = full.iter().find(|(name, _)| *name == lib).map(|(_, v)| v.clone()).ok_or_else(unknown)?;
+        let Some(func) = func else {
+            globals.set(lib, value)?;
+            continue;
+        };
+        let Value::Table(lib_table) = value else { return Err(unknown()) };
+        let f: Value = lib_table.get(func)?;
+        if f.is_nil() { return Err(unknown()); }
+        let granted = match globals.get::<_, Value>(lib)? {
+            Value::Table(t) => t,
+            _ => {
+                let t = lua.create_table()?;
+                globals.set(lib, t.clone())?;
+                t
+            }
+        };
+        granted.set(func, f)?;
+    }
+    Ok(())
+}
+
+/// Apply `lua.memory_mb`, and `lua.timeout_ms`/`lua.instructions` through an instruction hook. Each
+/// call into Lua starts a fresh budget with [`start_budget`].
+fn limit(lua: &Lua, cfg: &LuaConfig) -> Result<()> {
+    if cfg.memory_mb > 0 {
+        lua.set_memory_limit(usize::try_from(cfg.memory_mb.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX))?;
+    }
+    let (timeout_ms, max) = (cfg.timeout_ms, cfg.instructions);
+    if timeout_ms == 0 && max == 0 { return Ok(()); }
+    lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_EVERY), budget_hook(timeout_ms, max));
+    // mlua runs the hook on one thread at a time, so move it into each coroutine while it runs. Moving
+    // it restarts the hook's count, so each switch is counted and checked here too.
+    let retarget = lua.create_function(move |_, co: Thread| {
+        co.set_hook(HookTriggers::new().every_nth_instruction(HOOK_EVERY), budget_hook(timeout_ms, max));
+        check_budget(timeout_ms, max, 1)
+    })?;
+    let check = lua.create_function(move |_, ()| check_budget(timeout_ms, max, 0))?;
+    lua.load(KEEP_LIMITS).set_name("=mk").call::<_, ()>((retarget, check))?;
+    Ok(())
+}
+
+/// Keeps the limits in force: `coroutine.resume`/`coroutine.wrap` carry the instruction hook into the
+/// coroutine, and `pcall`/`xpcall` pass a limit error on instead of letting the script catch it.
+const KEEP_LIMITS: &str = r#"
+local retarget, check = ...
+local resume, running, create = coroutine.resume, coroutine.running, coroutine.create
+local pcall, xpcall, error = pcall, xpcall, error
+local function hooked_resume(co, ...)
+  local caller = running()
+  retarget(co)
+  local function back(...) retarget(caller); return ... end
+  return back(resume(co, ...))
+end
+coroutine.resume = hooked_resume
+coroutine.wrap = function(f)
+  local co = create(f)
+  return function(...)
+    local function unwrap(ok, ...)
+      if ok then return ... end
+      error((...), 0)
+    end
+    return unwrap(hooked_resume(co, ...))
+  end
+end
+local function recheck(ok, ...)
+  if not ok then check() end
+  return ok, ...
+end
+_G.pcall = function(...) return recheck(pcall(...)) end
+_G.xpcall = function(...) return recheck(xpcall(...)) end
+"#;
+
+/// The instruction hook enforcing `timeout_ms` and `instructions` against the current [`BUDGET`].
+fn budget_hook(timeout_ms: u64, max: u64) -> impl Fn(&Lua, Debug) -> mlua::Result<()> {
+    move |_, _| check_budget(timeout_ms, max, u64::from(HOOK_EVERY))
+}
+
+/// Count `ran` more instructions and fail once a limit is exceeded.
+fn check_budget(timeout_ms: u64, max: u64, ran: u64) -> mlua::Result<()> {
+    let (start, used) = BUDGET.with(|b| {
+        let (start, used) = b.get();
+        b.set((start, used + ran));
+        (start, used + ran)
+    });
+    if timeout_ms > 0 && start.elapsed() > Duration::from_millis(timeout_ms) {
+        return Err(mlua::Error::runtime(format!("script ran longer than {timeout_ms} ms (lua.timeout_ms)")));
+    }
+    if max > 0 && used > max {
+        return Err(mlua::Error::runtime(format!("script ran more than {max} instructions (lua.instructions)")));
+    }
+    Ok(())
+}
+
+fn start_budget() {
+    BUDGET.with(|b| b.set((Instant::now(), 0)));
+}
+
+/// A Lua error for the user; running out of memory names the limit.
+fn describe(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::MemoryError(_) => format!("script used more than {} MiB of memory (lua.memory_mb)", settings().1.memory_mb),
+        mlua::Error::CallbackError { cause, .. } if matches!(**cause, mlua::Error::MemoryError(_)) => describe(cause),
+        e => e.to_string(),
     }
+}
+
+/// Project-local scripts the user chose to always run, one `sha256:HASH PATH` line each. The hash covers
+/// the script's content, so an edited script is asked about again.
+fn trust_store() -> Option<PathBuf> {
+    Some(config_dir()?.join("mk").join("trusted_scripts"))
+}
+
+/// SHA-256 of a script, as written to the trust store.
+fn content_hash(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    format!("sha256:{}", digest.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+/// Split a trust store line into its hash and path. Lines in an older format (a bare path, or a
+/// 64-bit hash) are not entries and trust nothing.
+fn trust_entry(line: &str) -> Option<(&str, &Path)> {
+    let (hash, path) = line.trim().split_once(' ')?;
+    let hex = hash.strip_prefix("sha256:")?;
+    (hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit())).then(|| (hash, Path::new(path)))
+}
 
-    m
+/// Whether a trust store line, in any format, is about `script`.
+fn names_script(line: &str, script: &Path) -> bool {
+    let line = line.trim();
+    Path::new(line) == script || line.split_once(' ').is_some_and(|(_, path)| Path::new(path) == script)
+}
+
+/// Whether the project-local `script` may run: listed in the trust store with its current content, or
+/// approved on the terminal now. Untrusted scripts are skipped with a warning.
+fn trusted(script: &Path) -> Result<bool> {
+    let abs = fs::canonicalize(script).with_context(|| format!("resolving {}", script.display()))?;
+    let hash = content_hash(&fs::read(&abs).with_context(|| format!("reading Lua script {}", abs.display()))?);
+    let store = trust_store();
+    let listed = store.as_ref().and_then(|p| fs::read_to_string(p).ok()).unwrap_or_default();
+    let known: Vec<_> = listed.lines().filter_map(trust_entry).filter(|(_, p)| *p == abs).collect();
+    if known.iter().any(|(h, _)| *h == hash) { return Ok(true); }
+
+    let question = if known.is_empty() {
+        format!("mk: {} has not been trusted yet; running it can execute code. Run it?", abs.display())
+    } else {
+        format!("mk: {} changed since it was trusted; running it can execute code. Run it?", abs.display())
+    };
+    let choices = ["once".to_string(), "always".to_string()];
+    match prompt::pick("trust", &question, &choices)?.as_deref() {
+        Some("always") => {
+            if let Some(store) = store {
+                if let Some(dir) = store.parent() { fs::create_dir_all(dir)?; }
+                let mut lines: Vec<&str> = listed.lines().filter(|l| !names_script(l, &abs)).collect();
+                let entry = format!("{hash} {}", abs.display());
+                lines.push(&entry);
+                fs::write(&store, lines.join("\n") + "\n").with_context(|| format!("writing {}", store.display()))?;
+            }
+            Ok(true)
+        }
+        Some(_) => Ok(true),
+        None => {
+            eprintln!("mk: warning: skipped untrusted {} (run mk in a terminal to approve it)", abs.display());
+            Ok(false)
+        }
+    }
 }
 
 fn value_to_string(v: &Value) -> Option<String> {
@@ -104,10 +563,176 @@ fn value_to_string(v: &Value) -> Option<String> {
     }
 }
 
-pub fn apply_placeholders(mut content: String, map: &std::collections::HashMap<String, String>) -> String {
-    for (k, v) in map.iter() {
-        let token = format!("<{{&{}&}}>", k);
-        content = content.replace(&token, v);
+/// Convert a Lua value into a template value. Sequences become lists, other tables become maps.
+fn lua_to_value(v: &Value) -> Option<engine::Value> {
+    let Value::Table(t) = v else { return value_to_string(v).map(engine::Value::Str) };
+    if t.raw_len() > 0 {
+        let items = t.clone().sequence_values::<Value>().flatten().filter_map(|v| lua_to_value(&v)).collect();
+        return Some(engine::Value::List(items));
+    }
+    let fields: BTreeMap<String, engine::Value> = t.clone().pairs::<String, Value>().flatten()
+    .filter_map(|(k, v)| Some((k, lua_to_value(&v)?)))
+    .collect();
+    Some(engine::Value::Map(fields))
+}
+
+fn value_to_lua<'lua>(lua: &'lua Lua, v: &engine::Value) -> mlua::Result<Value<'lua>> {
+    Ok(match v {
+        engine::Value::Str(s) => Value::String(lua.create_string(s)?),
+        engine::Value::List(items) => {
+            let t = lua.create_table()?;
+            for item in items { t.push(value_to_lua(lua, item)?)?; }
+            Value::Table(t)
+        }
+        engine::Value::Map(fields) => {
+            let t = lua.create_table()?;
+            for (k, v) in fields { t.set(k.as_str(), value_to_lua(lua, v)?)?; }
+            Value::Table(t)
+        }
+    })
+}
+
+/// Filters registered by the placeholder script in `mk.filters`.
+pub struct LuaFilters;
+
+impl FilterHost for LuaFilters {
+    fn call(&self, name: &str, input: &engine::Value, args: &[String]) -> Option<Result<engine::Value, String>> {
+        // A script error has already been reported by the time filters run
+        lua_placeholders_cached().ok()?;
+        LUA_VM.with(|vm| {
+            let (lua, _) = vm.get()?;
+            let filters: Table = lua.globals().get::<_, Table>("mk").ok()?.get("filters").ok()?;
+            let func: Function = filters.get(name).ok()?;
+            start_budget();
+            let call = || -> mlua::Result<engine::Value> {
+                let mut params = vec![value_to_lua(lua, input)?];
+                for a in args { params.push(Value::String(lua.create_string(a)?)); }
+                let out: Value = func.call(Variadic::from_iter(params))?;
+                Ok(lua_to_value(&out).unwrap_or(engine::Value::Str(String::new())))
+            };
+            Some(call().map_err(|e| describe(&e)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowing(allow: &[&str]) -> Lua {
+        let lua = Lua::new();
+        sandbox(&lua, &allow.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap();
+        lua
+    }
+
+    fn eval_bool(lua: &Lua, src: &str) -> bool {
+        lua.load(src).eval().unwrap_or_else(|e| panic!("`{src}` failed: {e}"))
+    }
+
+    #[test]
+    fn sandbox_removes_system_access() {
+        let lua = allowing(&[]);
+        assert!(eval_bool(&lua, "return os == nil and io == nil and package == nil and require == nil"));
+        assert!(eval_bool(&lua, "return dofile == nil and loadfile == nil"));
+        assert!(eval_bool(&lua, "return string.upper('a') == 'A' and math.max(1, 2) == 2 and type(coroutine.wrap) == 'function'"));
+    }
+
+    #[test]
+    fn allow_grants_only_what_it_names() {
+        let lua = allowing(&["os.date"]);
+        assert!(eval_bool(&lua, "return type(os.date) == 'function' and os.getenv == nil and os.execute == nil and io == nil"));
+        let lua = allowing(&["io.*", "require"]);
+        assert!(eval_bool(&lua, "return type(io.open) == 'function' and type(require) == 'function' and os == nil"));
+        for bad in ["os.nope", "nope", "string"] {
+            assert!(sandbox(&Lua::new(), &[bad.to_string()]).is_err(), "`{bad}` was accepted");
+        }
+    }
+
+    #[test]
+    fn load_refuses_binary_chunks() {
+        let dump = "local chunk = string.dump(function() return 1 end) ";
+        let lua = allowing(&[]);
+        let err: String = lua.load(format!("{dump} local f, err = load(chunk) return f == nil and err")).eval().unwrap();
+        assert!(err.contains("binary"), "{err}");
+        assert!(eval_bool(&lua, "return load('return 2')() == 2"));
+        assert!(eval_bool(&lua, "return load('return x', 'chunk', 't', { x = 3 })() == 3"));
+        let lua = allowing(&["load"]);
+        assert!(eval_bool(&lua, &format!("{dump} return load(chunk)() == 1")));
+    }
+
+    /// A sandboxed VM with `cfg`'s limits, as [`lua_placeholders`] sets it up.
+    fn vm(cfg: LuaConfig) -> Lua {
+        let lua = Lua::new();
+        sandbox(&lua, &cfg.allow).unwrap();
+        limit(&lua, &cfg).unwrap();
+        lua
+    }
+
+    /// Run `src` with a fresh budget and return the limit error it stopped with.
+    fn stopped(lua: &Lua, src: &str) -> String {
+        start_budget();
+        match lua.load(src).exec() {
+            Ok(()) => panic!("script was not stopped: {src}"),
+            Err(e) => {
+                let msg = describe(&e);
+                assert!(msg.contains("(lua."), "`{src}` failed without naming a limit: {msg}");
+                msg
+            }
+        }
+    }
+
+    fn timeout(ms: u64) -> LuaConfig { LuaConfig { timeout_ms: ms, memory_mb: 0, ..Default::default() } }
+
+    fn instructions(max: u64) -> LuaConfig { LuaConfig { timeout_ms: 0, memory_mb: 0, instructions: max, ..Default::default() } }
+
+    #[test]
+    fn limits_stop_runaway_scripts() {
+        assert!(stopped(&vm(timeout(100)), "while true do end").contains("lua.timeout_ms"));
+        assert!(stopped(&vm(instructions(100_000)), "while true do end").contains("lua.instructions"));
+        let lua = vm(LuaConfig { timeout_ms: 0, memory_mb: 1, ..Default::default() });
+        start_budget();
+        let err = lua.load("local t = {} for i = 1, 1e8 do t[i] = i end").exec().unwrap_err();
+        assert!(matches!(err, mlua::Error::MemoryError(_)), "{err}");
+
+        let lua = vm(instructions(100_000));
+        start_budget();
+        lua.load("local n = 0 for i = 1, 1000 do n = n + i end").exec().unwrap();
+    }
+
+    #[test]
+    fn limits_hold_inside_coroutines() {
+        for cfg in [timeout(100), instructions(100_000)] {
+            let lua = vm(cfg);
+            stopped(&lua, "coroutine.wrap(function() while true do end end)()");
+            stopped(&lua, "local co = coroutine.create(function() while true do end end) coroutine.resume(co)");
+            stopped(&lua, "local co = coroutine.create(function() while true do coroutine.yield() end end) while true do coroutine.resume(co) end");
+        }
+    }
+
+    #[test]
+    fn pcall_cannot_catch_a_limit() {
+        for cfg in [timeout(100), instructions(100_000)] {
+            let lua = vm(cfg);
+            stopped(&lua, "pcall(function() while true do end end)");
+            stopped(&lua, "while true do pcall(function() while true do end end) end");
+            stopped(&lua, "xpcall(function() while true do end end, function(e) return e end)");
+        }
+        let lua = vm(instructions(100_000));
+        start_budget();
+        let caught: bool = lua.load("return not pcall(error, 'x')").eval().unwrap();
+        assert!(caught, "pcall no longer catches ordinary errors");
+    }
+
+    #[test]
+    fn trust_entries_need_a_sha256() {
+        let hash = content_hash(b"abc");
+        assert_eq!(hash, "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        let line = format!("{hash} /p/mk_placeholders.lua");
+        assert_eq!(trust_entry(&line), Some((hash.as_str(), Path::new("/p/mk_placeholders.lua"))));
+        assert_eq!(trust_entry("/p/mk_placeholders.lua"), None);
+        assert_eq!(trust_entry("231ff7355824064f /p/mk_placeholders.lua"), None);
+        assert!(names_script("231ff7355824064f /p/mk_placeholders.lua", Path::new("/p/mk_placeholders.lua")));
+        assert!(names_script("/p/mk_placeholders.lua", Path::new("/p/mk_placeholders.lua")));
+        assert!(!names_script(&line, Path::new("/q/mk_placeholders.lua")));
     }
-    content
 }
@@ -0,0 +1,89 @@
+// path: crates/mk/src/prompt.rs
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, IsTerminal, Write},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{engine::{PromptSpec, Value, Vars}, error::MkError};
+
+thread_local! {
+    /// Answers given so far, so a variable shared by several targets is only asked once
+    static ANSWERS: RefCell<Vars> = RefCell::new(Vars::new());
+    /// Choices made with [`pick`], by question key
+    static PICKS: RefCell<Vars> = RefCell::new(Vars::new());
+}
+
+/// Fill in every prompt whose variable is not already in `vars`, asking on the terminal.
+pub fn fill(specs: &[PromptSpec], vars: &mut Vars) -> Result<()> {
+    for spec in specs {
+        if vars.contains_key(&spec.name) { continue; }
+        let answer = ask(spec)?;
+        vars.insert(spec.name.clone(), answer);
+    }
+    Ok(())
+}
+
+/// Whether missing variables can be asked for interactively.
+pub fn interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+fn ask(spec: &PromptSpec) -> Result<Value> {
+    if let Some(v) = ANSWERS.with(|a| a.borrow().get(&spec.name).cloned()) { return Ok(v); }
+    if !interactive() {
+        return Err(MkError::NoTerminal(spec.name.clone()).into());
+    }
+
+    let mut question = spec.text.clone().unwrap_or_else(|| spec.name.clone());
+    if !spec.choices.is_empty() { question.push_str(&format!(" ({})", spec.choices.join("/"))); }
+    if let Some(d) = &spec.default { question.push_str(&format!(" [{d}]")); }
+
+    let stdin = io::stdin();
+    let answer = loop {
+        eprint!("{question}: ");
+        io::stderr().flush().ok();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).context("reading answer")? == 0 {
+            return Err(MkError::NoTerminal(spec.name.clone()).into());
+        }
+        let line = line.trim();
+        let answer = if line.is_empty() { spec.default.clone().unwrap_or_default() } else { line.to_string() };
+        if spec.choices.is_empty() || spec.choices.contains(&answer) { break answer; }
+        // Also accept the 1-based index of a choice
+        if let Some(choice) = answer.parse::<usize>().ok().and_then(|i| spec.choices.get(i.wrapping_sub(1))) {
+            break choice.clone();
+        }
+        eprintln!("mk: please answer one of: {}", spec.choices.join(", "));
+    };
+
+    let value = Value::Str(answer);
+    ANSWERS.with(|a| a.borrow_mut().insert(spec.name.clone(), value.clone()));
+    Ok(value)
+}
+
+/// Let the user pick one of `choices` by number or name; an empty answer declines. Returns `None`
+/// without asking when stdin is not a terminal. Picks are remembered by `key`.
+pub fn pick(key: &str, question: &str, choices: &[String]) -> Result<Option<String>> {
+    if let Some(v) = PICKS.with(|p| p.borrow().get(key).cloned()) { return Ok(Some(v.to_text())); }
+    if choices.is_empty() || !interactive() { return Ok(None); }
+
+    eprintln!("{question}");
+    for (i, c) in choices.iter().enumerate() { eprintln!("  {}) {c}", i + 1); }
+    let stdin = io::stdin();
+    let choice = loop {
+        eprint!("Pick a number (Enter to cancel): ");
+        io::stderr().flush().ok();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).context("reading answer")? == 0 { return Ok(None); }
+        let line = line.trim();
+        if line.is_empty() { return Ok(None); }
+        if let Some(c) = line.parse::<usize>().ok().and_then(|i| choices.get(i.wrapping_sub(1))).or_else(|| choices.iter().find(|c| *c == line)) {
+            break c.clone();
+        }
+        eprintln!("mk: please answer 1-{}", choices.len());
+    };
+    PICKS.with(|p| p.borrow_mut().insert(key.to_string(), Value::Str(choice.clone())));
+    Ok(Some(choice))
+}
@@ -0,0 +1,312 @@
+// path: crates/mk/src/registry.rs
+//! One lookup over both kinds of template: config templates (`[templates.<key>]` in config.toml)
+//! and file templates (files in the directories of `templater::search_path()`).
+//!
+//! Precedence for an explicit `-t NAME`:
+//! 1. a file template named exactly `NAME` (e.g. `python.py`)
+//! 2. a file template whose stem is `NAME` (e.g. `python` for `python.py`, `react/component` for
+//!    `react/component.tsx`)
+//! 3. the config template with key `NAME`
+//! 4. a config template whose `ext` is `NAME`
+//!
+//! Without `-t`, templates are only picked when `extension_check = true`. Top-level file templates
+//! match by front matter `extensions`/`globs`/`patterns` (else their own extension), config templates
+//! by their key as an extension or their `ext` entries. The most specific match wins (see
+//! [`crate::pattern`]), after any `default_for` entry and before `priority`; remaining ties go to
+//! file templates (search path order, then name) before config templates.
+//!
+//! A directory on the search path with a `scaffold.toml` is a scaffold: `-t NAME` renders every file
+//! in it. Other directories are namespaces (`-t react/component`).
+//!
+//! Exactly one template is used per target, and every template sees the same variables.
+use std::{fmt, path::{Path, PathBuf}};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    config::{Config, Template},
+    frontmatter,
+    pattern::{Pattern, Specificity},
+    scaffold::Scaffold,
+    templater::{self, Layer, MatchReason},
+};
+
+/// Where a template was defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// `[templates.<key>]` in config.toml (including the built-in defaults)
+    Config(String),
+    /// A file or scaffold directory on the template search path
+    File(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Config(key) => write!(f, "config template `{key}`"),
+            Source::File(path) if path.is_dir() => write!(f, "{}/ (scaffold)", path.display()),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The template chosen for a target.
+#[derive(Debug, Clone)]
+pub enum Resolved<'a> {
+    Config { key: &'a str, template: &'a Template },
+    File(PathBuf),
+    /// A directory on the template search path, copied and rendered as a whole
+    Scaffold(PathBuf),
+}
+
+impl Resolved<'_> {
+    pub fn source(&self) -> Source {
+        match self {
+            Resolved::Config { key, .. } => Source::Config(key.to_string()),
+            Resolved::File(path) | Resolved::Scaffold(path) => Source::File(path.clone()),
+        }
+    }
+
+    /// The config template key, or the template file or scaffold path.
+    pub fn name(&self) -> String {
+        match self {
+            Resolved::Config { key, .. } => key.to_string(),
+            Resolved::File(path) | Resolved::Scaffold(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// Ordering of candidates for a target; greater wins. Ties keep lookup order: file templates
+/// (search path order, then name) before config templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rank {
+    /// Named by a `default_for` entry (config.toml or front matter)
+    pub default_for: bool,
+    pub specificity: Specificity,
+    pub priority: i32,
+}
+
+impl Rank {
+    const EXPLICIT: Self = Self { default_for: false, specificity: Specificity::EXPLICIT, priority: 0 };
+}
+
+/// A template that matched a target, and why.
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub template: Resolved<'a>,
+    pub reason: MatchReason,
+    pub rank: Rank,
+}
+
+/// Every template that matches a target, best first; the first one is used.
+#[derive(Debug, Clone, Default)]
+pub struct Candidates<'a> {
+    pub list: Vec<Candidate<'a>>,
+    /// Templates left out because they could not be read, with the error
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl<'a> Candidates<'a> {
+    pub fn chosen(&self) -> Option<Resolved<'a>> {
+        self.list.first().map(|c| c.template.clone())
+    }
+
+    /// Candidates that tie with the chosen one and were only told apart by lookup order.
+    pub fn ties(&self) -> Vec<&Candidate<'a>> {
+        let Some(first) = self.list.first() else { return vec![] };
+        let ties: Vec<_> = self.list.iter().filter(|c| c.rank == first.rank).collect();
+        if ties.len() > 1 { ties } else { vec![] }
+    }
+}
+
+fn file_template(path: PathBuf) -> Resolved<'static> {
+    if path.is_dir() { Resolved::Scaffold(path) } else { Resolved::File(path) }
+}
+
+/// Look up every template for `target`. File templates are skipped when `files` is false
+/// (`--no-template`).
+pub fn candidates<'a>(cfg: &'a Config, target: &Path, explicit: Option<&str>, files: bool) -> Result<Candidates<'a>> {
+    let mut list = Vec::new();
+    if let Some(name) = explicit {
+        if files {
+            if let Some((path, reason)) = templater::find_template(name)? {
+                list.push(Candidate { template: file_template(path), reason, rank: Rank::EXPLICIT });
+            }
+        }
+        if let Some((key, template)) = cfg.get_template(name) {
+            let reason = if key == name { MatchReason::Key } else { MatchReason::Extension(name.to_string()) };
+            list.push(Candidate { template: Resolved::Config { key, template }, rank: Rank { specificity: reason.specificity(), ..Rank::default() }, reason });
+        }
+        return Ok(Candidates { list, ..Default::default() });
+    }
+    if !cfg.extension_check { return Ok(Candidates::default()); }
+
+    let name = target.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    for (entry, tname) in &cfg.default_for {
+        let Some(m) = templater::entry_match(entry, name).with_context(|| format!("in `default_for` entry `{entry}`"))? else { continue };
+        let rank = Rank { default_for: true, specificity: m.specificity(), priority: 0 };
+        let reason = MatchReason::DefaultFor(entry.clone());
+        let template = match (templater::find_template(tname)?, cfg.get_template(tname)) {
+            (Some((path, _)), _) if files => file_template(path),
+            (_, Some((key, template))) => Resolved::Config { key, template },
+            // A file template while file templates are off (`--no-template`): the entry does not apply
+            (Some(_), None) => continue,
+            (None, None) => bail!("`default_for` entry `{entry}` names unknown template `{tname}`"),
+        };
+        list.push(Candidate { template, reason, rank });
+    }
+    let mut skipped = Vec::new();
+    if files {
+        for (path, reason, rank) in templater::implicit_matches(target, &mut skipped) {
+            list.push(Candidate { template: file_template(path), reason, rank });
+        }
+    }
+    for (key, template) in &cfg.templates {
+        if let Some(reason) = config_match(key, template, name)? {
+            let rank = Rank { default_for: false, specificity: reason.specificity(), priority: template.priority };
+            list.push(Candidate { template: Resolved::Config { key, template }, reason, rank });
+        }
+    }
+
+    list.sort_by_key(|c| std::cmp::Reverse(c.rank));
+    let mut seen = Vec::new();
+    list.retain(|c| { let s = c.template.source(); let new = !seen.contains(&s); seen.push(s); new });
+    Ok(Candidates { list, skipped })
+}
+
+/// The most specific way config template `key` matches `name`: its key as an extension, or its `ext` entries.
+fn config_match(key: &str, t: &Template, name: &str) -> Result<Option<MatchReason>> {
+    let mut best = Pattern::extension(key).matches(name).then(|| MatchReason::Extension(key.to_string()));
+    for entry in &t.ext {
+        let Some(reason) = templater::entry_match(entry, name).with_context(|| format!("in config template `{key}`"))? else { continue };
+        if best.as_ref().is_none_or(|b| reason.specificity() > b.specificity()) { best = Some(reason); }
+    }
+    Ok(best)
+}
+
+/// A template as shown by `--list-templates`. File templates are named by their path in the
+/// templates directory, e.g. `react/component.tsx`.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub source: Source,
+    /// Search path layer of a file template
+    pub layer: Option<Layer>,
+    /// Set when a template with the same name in a higher-priority layer hides this one
+    pub shadowed_by: Option<Layer>,
+    /// Extensions and patterns a config template is registered for
+    pub ext: Vec<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    /// Namespace of a file template (`react` for `react/component.tsx`), empty at the top level.
+    pub fn namespace(&self) -> &str {
+        self.name.rsplit_once('/').map_or("", |(ns, _)| ns)
+    }
+}
+
+/// Every known template, file templates first (they win on name clashes) in search path order.
+pub fn entries(cfg: &Config) -> Result<Vec<Entry>> {
+    let mut out: Vec<Entry> = Vec::new();
+    for templater::TemplatePath { layer, path, name } in templater::list_templates()? {
+        let (description, tags) = if path.is_dir() {
+            let m = Scaffold::load(&path).map(|s| s.manifest).unwrap_or_default();
+            (m.description, m.tags)
+        } else {
+            let m = frontmatter::read(&path).ok().flatten().unwrap_or_default();
+            (m.description, m.tags)
+        };
+        let shadowed_by = out.iter().find(|e| e.name == name).and_then(|e| e.layer);
+        out.push(Entry { name, source: Source::File(path), layer: Some(layer), shadowed_by, ext: vec![], description, tags });
+    }
+    for (key, t) in &cfg.templates {
+        out.push(Entry { name: key.clone(), source: Source::Config(key.clone()), layer: None, shadowed_by: None, ext: t.ext.clone(), description: None, tags: vec![] });
+    }
+    Ok(out)
+}
+
+/// Template names close to `name`, best first, for "did you mean" hints. Compares against config
+/// keys and `ext` words, file template names and stems, and the last segment of namespaced names, so
+/// `py` finds `python` and `component` finds `react/component`.
+pub fn suggest(cfg: &Config, name: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (key, t) in &cfg.templates {
+        names.push(key.clone());
+        names.extend(t.ext.iter().filter(|e| matches!(Pattern::parse(e), Ok(Pattern::Name(_)))).cloned());
+    }
+    for t in templater::list_templates().unwrap_or_default() {
+        // `-t` accepts stems, which are also the shortest way to name a file template
+        let stem = Path::new(&t.name).file_stem().map(|s| s.to_string_lossy().to_string());
+        names.push(match stem {
+            Some(stem) if t.path.is_file() && !t.namespace().is_empty() => format!("{}/{stem}", t.namespace()),
+            Some(stem) if t.path.is_file() => stem,
+            _ => t.name,
+        });
+    }
+
+    let input = name.to_lowercase();
+    let len = input.chars().count();
+    let limit = if len <= 5 { 2 } else { len / 3 };
+    let mut scored: Vec<(usize, String)> = names.into_iter().filter_map(|n| {
+        let full = n.to_lowercase();
+        let leaf = full.rsplit('/').next().unwrap_or(&full).to_string();
+        let score = [&full, &leaf].iter().filter_map(|k| {
+            let k_len = k.chars().count();
+            // A typed prefix (`py` for `python`) is a strong hint; otherwise the distance must be
+            // small and must not replace the whole name
+            if (len >= 2 && k.starts_with(&input)) || (k_len >= 3 && input.starts_with(k.as_str())) { return Some(1); }
+            let d = edit_distance(&input, k);
+            (d <= limit && d < len.max(k_len)).then_some(d)
+        }).min()?;
+        (full != input).then_some((score, n))
+    }).collect();
+    scored.sort();
+    let mut out: Vec<String> = Vec::new();
+    for (_, n) in scored {
+        if !out.contains(&n) { out.push(n); }
+    }
+    out.truncate(3);
+    out
+}
+
+/// Levenshtein distance.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != cb)).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_single_edits() {
+        assert_eq!(edit_distance("python", "python"), 0);
+        assert_eq!(edit_distance("pyhton", "python"), 2);
+        assert_eq!(edit_distance("rs", "rust"), 2);
+        assert_eq!(edit_distance("", "md"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggestions_for_prefixes_and_typos() {
+        let cfg: Config = toml::from_str("[templates.python]\nbody = \"\"\n[templates.markdown]\nbody = \"\"\next = [\"md\"]\n").unwrap();
+        assert!(suggest(&cfg, "py").contains(&"python".to_string()));
+        assert!(suggest(&cfg, "pyhton").contains(&"python".to_string()));
+        assert!(suggest(&cfg, "markdwn").contains(&"markdown".to_string()));
+        assert!(!suggest(&cfg, "python").contains(&"python".to_string()));
+        assert!(!suggest(&cfg, "go").contains(&"python".to_string()));
+    }
+}
@@ -0,0 +1,172 @@
+// path: crates/mk/src/scaffold.rs
+//! Rules for a directory scaffold: a `scaffold.toml` manifest, which marks a directory as a scaffold
+//! (it may be empty), and an optional `.mkignore`. Both live in the scaffold's root and are never
+//! copied to the output.
+//!
+//! ```toml
+//! description = "Rust library crate"
+//! tags = ["rust"]
+//!
+//! [vars.with_tests]
+//! type = "bool"
+//! default = false
+//!
+//! [[files]]
+//! path = "tests"            # glob against the path inside the scaffold; directories cover their contents
+//! when = "with_tests"       # same conditions as `#if`
+//!
+//! [[files]]
+//! path = "Cargo.lock"
+//! overwrite = "never"       # never | always | ask (default)
+//! ```
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    engine::{self, PromptSpec, Vars},
+    frontmatter::{self, VarSpec},
+    pattern::glob_match,
+};
+
+pub const MANIFEST: &str = "scaffold.toml";
+pub const IGNORE: &str = ".mkignore";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub vars: BTreeMap<String, VarSpec>,
+    #[serde(default)]
+    pub files: Vec<FileRule>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileRule {
+    pub path: String,
+    /// Condition for creating matching entries, e.g. `with_tests` or `lang == "rust"`
+    pub when: Option<String>,
+    #[serde(default)]
+    pub overwrite: Overwrite,
+}
+
+/// What to do when a scaffold file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overwrite {
+    /// Follow `-y`/`-n`, else ask
+    #[default]
+    Ask,
+    /// Keep the existing file
+    Never,
+    /// Regenerate it every time
+    Always,
+}
+
+/// The manifest and ignore patterns of one scaffold.
+#[derive(Debug, Clone, Default)]
+pub struct Scaffold {
+    pub manifest: Manifest,
+    ignore: Vec<String>,
+}
+
+impl Scaffold {
+    /// Read `scaffold.toml` and `.mkignore` from `root`; missing files count as empty.
+    pub fn load(root: &Path) -> Result<Self> {
+        let manifest_path = root.join(MANIFEST);
+        let manifest = match fs::read_to_string(&manifest_path) {
+            Ok(src) => toml::from_str(&src).with_context(|| format!("parsing {}", manifest_path.display()))?,
+            Err(_) => Manifest::default(),
+        };
+        let ignore = fs::read_to_string(root.join(IGNORE)).unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.trim_matches('/').to_string())
+        .collect();
+        Ok(Self { manifest, ignore })
+    }
+
+    /// Apply the manifest's variable defaults and return prompts for missing required ones.
+    pub fn apply_defaults(&self, vars: &mut Vars) -> Vec<PromptSpec> {
+        frontmatter::apply_var_defaults(&self.manifest.vars, vars)
+    }
+
+    pub fn coerce(&self, vars: &mut Vars) -> Result<()> {
+        frontmatter::coerce_vars(&self.manifest.vars, vars)
+    }
+
+    /// Whether `rel` (a path inside the scaffold) is left out: the manifest and ignore file themselves,
+    /// anything in `.mkignore`, and anything whose `when` condition is false.
+    pub fn skip(&self, rel: &Path, vars: &Vars) -> Result<bool> {
+        if rel == Path::new(MANIFEST) || rel == Path::new(IGNORE) { return Ok(true); }
+        if self.ignore.iter().any(|p| path_matches(p, rel)) { return Ok(true); }
+        for rule in self.manifest.files.iter().filter(|r| path_matches(&r.path, rel)) {
+            let Some(cond) = &rule.when else { continue };
+            let keep = engine::eval_condition(cond, vars).with_context(|| format!("in `when` for `{}` in {MANIFEST}", rule.path))?;
+            if !keep { return Ok(true); }
+        }
+        Ok(false)
+    }
+
+    /// Overwrite policy for `rel`; the last matching rule that sets one wins.
+    pub fn overwrite(&self, rel: &Path) -> Overwrite {
+        self.manifest.files.iter().rev()
+        .filter(|r| r.overwrite != Overwrite::Ask && path_matches(&r.path, rel))
+        .map(|r| r.overwrite)
+        .next()
+        .unwrap_or_default()
+    }
+}
+
+/// Match a `/`-separated glob against `rel` or any of its parent directories. Patterns without a `/`
+/// also match a bare file or directory name at any depth, as in `.gitignore`.
+fn path_matches(pattern: &str, rel: &Path) -> bool {
+    let parts: Vec<String> = rel.components()
+    .filter_map(|c| match c { Component::Normal(s) => Some(s.to_string_lossy().to_string()), _ => None })
+    .collect();
+    let pattern = pattern.trim_matches('/');
+    (1..=parts.len()).any(|n| {
+        glob_match(pattern, &parts[..n].join("/")) || (!pattern.contains('/') && glob_match(pattern, &parts[n - 1]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_match_like_gitignore() {
+        assert!(path_matches("tests", Path::new("tests")));
+        assert!(path_matches("tests", Path::new("tests/it.rs")));
+        assert!(path_matches("tests/", Path::new("tests/it.rs")));
+        assert!(path_matches("*.lock", Path::new("sub/Cargo.lock")));
+        assert!(path_matches("src/*.rs", Path::new("src/lib.rs")));
+        assert!(!path_matches("src/*.rs", Path::new("other/src/lib.rs")));
+        assert!(!path_matches("tests", Path::new("src/tests.rs")));
+    }
+
+    #[test]
+    fn skip_follows_ignore_and_when() {
+        let manifest: Manifest = toml::from_str("[[files]]\npath = \"tests\"\nwhen = \"with_tests\"\n[[files]]\npath = \"Cargo.lock\"\noverwrite = \"never\"\n").unwrap();
+        let rules = Scaffold { manifest, ignore: vec!["fixtures".to_string()] };
+        let vars = |on: bool| -> Vars { [("with_tests".to_string(), on.to_string().into())].into() };
+        for always in [MANIFEST, IGNORE, "fixtures/a.txt"] {
+            assert!(rules.skip(Path::new(always), &vars(true)).unwrap(), "{always} was kept");
+        }
+        assert!(rules.skip(Path::new("tests/it.rs"), &vars(false)).unwrap());
+        assert!(!rules.skip(Path::new("tests/it.rs"), &vars(true)).unwrap());
+        assert!(!rules.skip(Path::new("src/lib.rs"), &vars(false)).unwrap());
+        assert_eq!(rules.overwrite(Path::new("Cargo.lock")), Overwrite::Never);
+        assert_eq!(rules.overwrite(Path::new("src/lib.rs")), Overwrite::Ask);
+    }
+}
@@ -1,14 +1,24 @@
 // path: crates/mk/src/template.rs
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use chrono::Local;
 
+use crate::engine::Vars;
+
 #[derive(Debug, Clone)]
 pub struct ContextVars {
+    /// The target as given on the command line
+    pub path: PathBuf,
+    /// The template being rendered: a config template key or a template file path
+    pub template: Option<String>,
     pub date: String,
+    pub time: String,
+    pub datetime: String,
     pub year: String,
     pub author: String,
     pub file_name: String,
     pub file_stem: String,
+    /// Variables from `-D`/`--vars-file`; these override everything else
+    pub vars: Vars,
 }
 
 impl ContextVars {
@@ -18,26 +28,48 @@ impl ContextVars {
         let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
         let now = Local::now();
         Self {
+            path: p.to_path_buf(),
+            template: None,
             date: now.format("%Y-%m-%d").to_string(),
+            time: now.format("%H:%M:%S").to_string(),
+            datetime: now.format("%Y-%m-%d %H:%M:%S").to_string(),
             year: now.format("%Y").to_string(),
             author: author.unwrap_or("").to_string(),
             file_name: name,
             file_stem: stem,
+            vars: Vars::new(),
         }
     }
+
+    pub fn with_vars(mut self, vars: &Vars) -> Self {
+        self.vars = vars.clone();
+        self
+    }
+
+    pub fn with_template(mut self, template: Option<String>) -> Self {
+        self.template = template;
+        self
+    }
 }
 
-pub fn render(body: &str, ctx: &ContextVars) -> String {
-    // Tiny, fast placeholder replacement. Supports {date},{year},{author},{file_name},{file_stem}
-    let mut out = body.to_string();
-    for (k, v) in [
-        ("{date}", &ctx.date),
-        ("{year}", &ctx.year),
-        ("{author}", &ctx.author),
-        ("{file_name}", &ctx.file_name),
-        ("{file_stem}", &ctx.file_stem),
-    ] {
-        out = out.replace(k, v);
+impl ContextVars {
+    /// Built-in variables, under both the `{file_stem}` and the `<{&FILESTEM&}>` spelling.
+    /// `AUTHOR` is left out when no author is configured so it can still come from Lua or be asked for.
+    pub fn builtins(&self) -> Vars {
+        let mut m = Vars::new();
+        for (lower, upper, value) in [
+            ("date", "DATE", &self.date),
+            ("time", "TIME", &self.time),
+            ("datetime", "DATETIME", &self.datetime),
+            ("year", "YEAR", &self.year),
+            ("file_name", "FILENAME", &self.file_name),
+            ("file_stem", "FILESTEM", &self.file_stem),
+        ] {
+            m.insert(lower.to_string(), value.as_str().into());
+            m.insert(upper.to_string(), value.as_str().into());
+        }
+        m.insert("author".to_string(), self.author.as_str().into());
+        if !self.author.is_empty() { m.insert("AUTHOR".to_string(), self.author.as_str().into()); }
+        m
     }
-    out
 }
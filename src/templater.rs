@@ -1,97 +1,595 @@
 // path: crates/mk/src/templater.rs
-use std::{fs, path::{Path, PathBuf}};
-use anyhow::{Context, Result};
+use std::{fs, path::{Component, Path, PathBuf}};
+use anyhow::{bail, Context, Result};
 use once_cell::sync::OnceCell;
-use crate::placeholder;
+use crate::{
+    config::{Config, StrictMode},
+    engine::{self, Output, PromptSpec, Syntax, Unresolved, Vars},
+    error::MkError,
+    frontmatter,
+    pattern::{Pattern, Specificity},
+    placeholder::{self, LuaFilters},
+    prompt,
+    registry::{Rank, Resolved, Source},
+    scaffold,
+    template::ContextVars,
+};
 
 
 use dirs::config_dir;
 
+/// The user's templates directory, `~/.config/mk/templates`.
 pub fn templates_dir() -> PathBuf { config_dir().unwrap_or_else(|| Path::new(".").to_path_buf()).join("mk").join("templates") }
 
-pub fn list_templates() -> Result<Vec<PathBuf>> {
-    let dir = templates_dir();
-    let mut out = vec![];
-    if let Ok(rd) = fs::read_dir(&dir) {
-        for e in rd.flatten() { let p = e.path(); if p.is_file() { out.push(p); } }
+/// A layer of the template search path, in priority order (earlier layers shadow later ones).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// `.mk/templates` in the current directory or the nearest ancestor that has one
+    Project,
+    /// Entries of `$MK_TEMPLATE_PATH`
+    Env,
+    /// `templates_dir()`
+    User,
+    /// `/usr/share/mk/templates`
+    System,
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Layer::Project => "project",
+            Layer::Env => "MK_TEMPLATE_PATH",
+            Layer::User => "user",
+            Layer::System => "system",
+        })
+    }
+}
+
+/// Every existing templates directory, highest priority first.
+pub fn search_path() -> Vec<(Layer, PathBuf)> {
+    let mut out = Vec::new();
+    if let Ok(cwd) = std::env::current_dir() {
+        if let Some(dir) = cwd.ancestors().map(|d| d.join(".mk").join("templates")).find(|d| d.is_dir()) {
+            out.push((Layer::Project, dir));
+        }
+    }
+    if let Some(paths) = std::env::var_os("MK_TEMPLATE_PATH") {
+        out.extend(std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()).map(|p| (Layer::Env, p)));
     }
+    out.push((Layer::User, templates_dir()));
+    #[cfg(unix)]
+    out.push((Layer::System, PathBuf::from("/usr/share/mk/templates")));
+    out.retain(|(_, d)| d.is_dir());
+    out
+}
+
+/// A template file or scaffold found on the search path. `name` is relative to its templates
+/// directory with `/` separators, so nested templates are namespaced (`react/component.tsx`).
+#[derive(Debug, Clone)]
+pub struct TemplatePath {
+    pub layer: Layer,
+    pub path: PathBuf,
+    pub name: String,
+}
+
+impl TemplatePath {
+    /// The namespace part of the name (`react` for `react/component.tsx`), empty at the top level.
+    pub fn namespace(&self) -> &str {
+        self.name.rsplit_once('/').map_or("", |(ns, _)| ns)
+    }
+}
+
+/// Template files and directory scaffolds along the search path, highest priority first and
+/// sorted by name within each directory. Subdirectories are namespaces and are listed recursively,
+/// unless they have a `scaffold.toml`, which makes them a scaffold.
+pub fn list_templates() -> Result<Vec<TemplatePath>> {
+    fn walk(layer: Layer, dir: &Path, prefix: &str, out: &mut Vec<TemplatePath>) {
+        let Ok(rd) = fs::read_dir(dir) else { return };
+        let mut paths: Vec<PathBuf> = rd.flatten().map(|e| e.path()).collect();
+        paths.sort();
+        for p in paths {
+            let name = format!("{prefix}{}", p.file_name().unwrap_or_default().to_string_lossy());
+            if prefix.is_empty() && name == PARTIALS && p.is_dir() { continue; }
+            if p.is_dir() && !p.join(scaffold::MANIFEST).is_file() {
+                walk(layer, &p, &format!("{name}/"), out);
+            } else if p.is_file() || p.is_dir() {
+                out.push(TemplatePath { layer, path: p, name });
+            }
+        }
+    }
+    let mut out = vec![];
+    for (layer, dir) in search_path() { walk(layer, &dir, "", &mut out); }
     Ok(out)
 }
 
+/// Directory on the template search path that holds partials for `<{&>NAME&}>`. It is not a
+/// namespace: partials are never used as templates on their own.
+const PARTIALS: &str = "partials";
+
 static TEMPLATES_CACHE: OnceCell<Vec<PathBuf>> = OnceCell::new();
+/// Top-level template files only, in search path order. Namespaced templates and scaffolds are
+/// only picked by name.
 fn cached_templates() -> Vec<PathBuf> {
     TEMPLATES_CACHE.get_or_init(|| {
-        let dir = templates_dir();
-        let mut out = vec![];
-        if let Ok(rd) = fs::read_dir(&dir) {
-            for e in rd.flatten() {
-                let p = e.path();
-                if p.is_file() { out.push(p); }
-            }
-        }
-        out
+        list_templates().unwrap_or_default().into_iter()
+        .filter(|t| t.namespace().is_empty() && t.path.is_file())
+        .map(|t| t.path)
+        .collect()
     }).clone()
 }
 
-pub fn resolve_template_for_input(path: &Path, explicit: Option<&str>, extension_check: bool) -> Result<Option<PathBuf>> {
-    let dir = templates_dir();
-    if !dir.is_dir() { return Ok(None); }
+/// Why a template was picked for a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchReason {
+    /// `-t` named the template file exactly
+    ExactName,
+    /// `-t` named the template's file stem
+    Stem,
+    /// `-t` named a config template key
+    Key,
+    /// The target has this extension, which is the template's own extension (or a config template's `ext`)
+    Extension(String),
+    /// The target has this extension, listed in the front matter `extensions`
+    FrontMatterExtension(String),
+    /// The target matched this `default_for` entry in config.toml
+    DefaultFor(String),
+    /// The target's file name matched this pattern (front matter `globs`/`patterns`, config `ext`,
+    /// or the name of a template without an extension)
+    Pattern(String),
+}
 
-    let list = cached_templates();
+impl MatchReason {
+    /// How specific the match is; implicit matches are ranked by this.
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            MatchReason::ExactName | MatchReason::Stem | MatchReason::Key => Specificity::EXPLICIT,
+            MatchReason::Extension(e) | MatchReason::FrontMatterExtension(e) => Pattern::extension(e).specificity(),
+            MatchReason::Pattern(p) | MatchReason::DefaultFor(p) => Pattern::parse(p).map(|p| p.specificity()).unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchReason::ExactName => write!(f, "exact name"),
+            MatchReason::Stem => write!(f, "file stem"),
+            MatchReason::Key => write!(f, "config key"),
+            MatchReason::Extension(e) => write!(f, "extension `.{e}`"),
+            MatchReason::FrontMatterExtension(e) => write!(f, "front matter extension `.{e}`"),
+            MatchReason::DefaultFor(p) => write!(f, "`default_for` entry `{p}`"),
+            MatchReason::Pattern(p) => write!(f, "pattern `{p}`"),
+        }
+    }
+}
 
-    if let Some(name) = explicit {
-        // exact filename match first
+/// Find the template named by `-t NAME` on the search path: an exact file or scaffold name first
+/// (a directory with a `scaffold.toml`), then a file stem (inside the namespace for `ns/name`), and
+/// last any directory of that name, which is used as a scaffold even without a `scaffold.toml`.
+pub fn find_template(name: &str) -> Result<Option<(PathBuf, MatchReason)>> {
+    for (_, dir) in search_path() {
         let exact = dir.join(name);
-        if exact.is_file() { return Ok(Some(exact)); }
-        // otherwise match by file_stem
-        for p in &list {
-            if let Some(stem) = p.file_stem().and_then(|s| s.to_str()) {
-                if stem == name { return Ok(Some(p.clone())); }
-            }
-            if let Some(fname) = p.file_name().and_then(|s| s.to_str()) {
-                if fname == name { return Ok(Some(p.clone())); }
+        if exact.is_file() || exact.join(scaffold::MANIFEST).is_file() { return Ok(Some((exact, MatchReason::ExactName))); }
+    }
+    let (ns, stem) = name.rsplit_once('/').unwrap_or(("", name));
+    for (_, dir) in search_path() {
+        let Ok(rd) = fs::read_dir(dir.join(ns)) else { continue };
+        let mut paths: Vec<PathBuf> = rd.flatten().map(|e| e.path()).filter(|p| p.is_file()).collect();
+        paths.sort();
+        if let Some(p) = paths.into_iter().find(|p| p.file_stem().and_then(|s| s.to_str()) == Some(stem)) {
+            return Ok(Some((p, MatchReason::Stem)));
+        }
+    }
+    Ok(search_path().into_iter().map(|(_, dir)| dir.join(name)).find(|p| p.is_dir()).map(|p| (p, MatchReason::ExactName)))
+}
+
+/// Match one `ext`/`default_for` entry against a file name. A plain word is an exact file name or an
+/// extension; anything else is a [`Pattern`].
+pub fn entry_match(entry: &str, name: &str) -> Result<Option<MatchReason>> {
+    Ok(match Pattern::parse(entry)? {
+        p @ Pattern::Name(_) if p.matches(name) => Some(MatchReason::Pattern(entry.to_string())),
+        Pattern::Name(_) => Pattern::extension(entry).matches(name).then(|| MatchReason::Extension(entry.to_string())),
+        p => p.matches(name).then(|| MatchReason::Pattern(entry.to_string())),
+    })
+}
+
+/// Every top-level template that would match `target` implicitly, in search path order. A template
+/// that cannot be read (not UTF-8, bad front matter) is left out and added to `skipped` with the error,
+/// so one broken file does not stop targets it has nothing to do with.
+pub fn implicit_matches(target: &Path, skipped: &mut Vec<(PathBuf, String)>) -> Vec<(PathBuf, MatchReason, Rank)> {
+    let mut out = Vec::new();
+    for p in cached_templates() {
+        match template_matches(&p, target) {
+            Ok(Some((reason, rank))) => out.push((p, reason, rank)),
+            Ok(None) => {}
+            Err(e) => skipped.push((p, format!("{e:#}"))),
+        }
+    }
+    out
+}
+
+/// Implicit match of template `tpl` for `target`: by the front matter's `extensions`/`globs`/`patterns`
+/// when it declares any, otherwise by the template's own file extension (or its whole name if it has
+/// none, e.g. `Dockerfile`). Returns the most specific matching rule, ranked with the front matter's
+/// `default_for` and `priority`.
+fn template_matches(tpl: &Path, target: &Path) -> Result<Option<(MatchReason, Rank)>> {
+    let name = target.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    let in_tpl = || format!("in template {}", tpl.display());
+    let meta = frontmatter::read(tpl)?.unwrap_or_default();
+    let mut found = Vec::new();
+    if !meta.extensions.is_empty() || !meta.globs.is_empty() || !meta.patterns.is_empty() {
+        for e in &meta.extensions {
+            if Pattern::extension(e).matches(name) { found.push(MatchReason::FrontMatterExtension(e.clone())); }
+        }
+        for p in meta.globs.iter().chain(&meta.patterns) {
+            if Pattern::parse(p).with_context(in_tpl)?.matches(name) { found.push(MatchReason::Pattern(p.clone())); }
+        }
+    } else {
+        match tpl.extension().and_then(|s| s.to_str()) {
+            Some(e) => if Pattern::extension(e).matches(name) { found.push(MatchReason::Extension(e.to_string())); },
+            None => {
+                let own = tpl.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                if own == name { found.push(MatchReason::Pattern(own.to_string())); }
             }
         }
-    } else if extension_check {
-        if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-            for p in &list {
-                if p.extension().and_then(|s| s.to_str()) == Some(ext) {
-                    return Ok(Some(p.clone()));
-                }
+    }
+    let Some(reason) = found.into_iter().rev().max_by_key(|r| r.specificity()) else { return Ok(None) };
+
+    let mut default_for = false;
+    for entry in &meta.default_for {
+        default_for |= entry_match(entry, name).with_context(in_tpl)?.is_some();
+    }
+    let rank = Rank { default_for, specificity: reason.specificity(), priority: meta.priority };
+    Ok(Some((reason, rank)))
+}
+
+/// A file or directory inside a scaffold, relative to the scaffold root.
+#[derive(Debug, Clone)]
+pub struct ScaffoldEntry {
+    pub rel: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Every entry of the scaffold at `root`, parents before their children.
+pub fn scaffold_entries(root: &Path) -> Result<Vec<ScaffoldEntry>> {
+    fn walk(root: &Path, dir: &Path, out: &mut Vec<ScaffoldEntry>) -> Result<()> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir).with_context(|| format!("reading scaffold {}", dir.display()))?
+        .flatten().map(|e| e.path()).collect();
+        paths.sort();
+        for p in paths {
+            let rel = p.strip_prefix(root).unwrap_or(&p).to_path_buf();
+            if p.is_dir() {
+                out.push(ScaffoldEntry { rel, is_dir: true });
+                walk(root, &p, out)?;
+            } else {
+                out.push(ScaffoldEntry { rel, is_dir: false });
             }
         }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    walk(root, root, &mut out)?;
+    Ok(out)
+}
+
+/// Expand `__KEY__` (or `__KEY|filter__`) in a scaffold file or directory name. Keys that are not
+/// defined are left alone, so names like `__init__.py` survive. An expanded name must still be a single
+/// path segment: a value with a separator, `..` or a root would write outside the target.
+pub fn expand_name(name: &str, vars: &Vars) -> Result<String> {
+    let mut out = String::new();
+    let mut expanded = false;
+    let mut rest = name;
+    while let Some(start) = rest.find("__") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("__") else { break };
+        let inner = &after[..end];
+        let key = inner.split('|').next().unwrap_or("");
+        if key.is_empty() || !vars.contains_key(key) {
+            out.push_str(&rest[..start + 2]);
+            rest = after;
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let nodes = engine::parse(&format!("<{{&{inner}&}}>"), Syntax::ANGLE, 1).with_context(|| format!("in file name `{name}`"))?;
+        out.push_str(&engine::render(&nodes, vars, &LuaFilters).with_context(|| format!("in file name `{name}`"))?.text);
+        expanded = true;
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    let mut parts = Path::new(&out).components();
+    if expanded && (out.contains(['/', '\\']) || !matches!((parts.next(), parts.next()), (Some(Component::Normal(_)), None))) {
+        bail!("file name `{name}` expands to `{out}`, which is not a single file or directory name");
+    }
+    Ok(out)
+}
+
+/// Variables every template sees: builtins, then Lua (when `lua` is set; the script's table, then its
+/// `placeholders(ctx)` for this target), then CLI definitions (highest precedence).
+pub fn template_vars(ctx: &ContextVars, lua: bool) -> Result<Vars> {
+    let mut map = ctx.builtins();
+    if lua {
+        map.extend(placeholder::lua_placeholders_for(ctx)?);
+    }
+    map.extend(ctx.vars.clone());
+    Ok(map)
+}
+
+/// A token left unresolved, with the template it came from.
+#[derive(Debug, Clone)]
+pub struct UnresolvedToken {
+    pub origin: String,
+    pub token: Unresolved,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub content: String,
+    pub unresolved: Vec<UnresolvedToken>,
+    /// Output path from the template's front matter, relative to the target's directory
+    pub output: Option<PathBuf>,
+    /// Mode from the config template or the file template's front matter, else the template file's
+    /// own mode if it is executable
+    pub mode: Option<String>,
+}
+
+impl Rendered {
+    fn from_output(origin: &str, out: Output) -> Self {
+        let unresolved = out.unresolved.into_iter()
+        .map(|token| UnresolvedToken { origin: token.origin.clone().unwrap_or_else(|| origin.to_string()), token })
+        .collect();
+        Self { content: out.text, unresolved, ..Default::default() }
+    }
+}
+
+/// Parse and render one template source. `#prompt` declarations are asked for first; when stdin is a
+/// terminal and `ask_missing` is set, any other undefined `<{&KEY&}>` is asked for too and the
+/// template is rendered again.
+pub fn render_source(origin: &str, src: &str, syntax: Syntax, vars: &mut Vars, ask_missing: bool) -> Result<Rendered> {
+    render_source_from(origin, src, 1, syntax, vars, ask_missing)
+}
+
+fn render_source_from(origin: &str, src: &str, first_line: usize, syntax: Syntax, vars: &mut Vars, ask_missing: bool) -> Result<Rendered> {
+    let nodes = engine::parse(src, syntax, first_line).with_context(|| format!("in {origin}"))?;
+    let nodes = include_partials(nodes, &mut vec![origin.to_string()]).with_context(|| format!("in {origin}"))?;
+    let nodes = inherit(origin, nodes).with_context(|| format!("in {origin}"))?;
+    prompt::fill(&engine::prompts(&nodes), vars).with_context(|| format!("in {origin}"))?;
+    let mut out = engine::render(&nodes, vars, &LuaFilters).with_context(|| format!("in {origin}"))?;
+
+    if ask_missing && prompt::interactive() {
+        let missing: Vec<PromptSpec> = out.unresolved.iter()
+        .filter(|u| u.token.starts_with("<{&") && !u.name.contains('.'))
+        .map(|u| PromptSpec { name: u.name.clone(), ..Default::default() })
+        .collect();
+        if !missing.is_empty() {
+            prompt::fill(&missing, vars)?;
+            out = engine::render(&nodes, vars, &LuaFilters).with_context(|| format!("in {origin}"))?;
+        }
+    }
+    Ok(Rendered::from_output(origin, out))
+}
+
+/// Resolve `#extends`: walk up to the root template and render it with every `#block` replaced by the
+/// nearest descendant's version. Content outside blocks in an extending template is ignored.
+fn inherit(origin: &str, mut nodes: Vec<engine::Node>) -> Result<Vec<engine::Node>> {
+    let mut chain = vec![origin.to_string()];
+    let mut overrides = std::collections::BTreeMap::new();
+    while let Some((parent, pos)) = engine::extends(&nodes).map(|(p, pos)| (p.to_string(), pos)) {
+        for (name, body) in engine::blocks(&nodes) { overrides.entry(name).or_insert(body); }
+        let Some((parent_origin, parent_nodes)) = load_named(&parent)? else {
+            bail!("{}:{}: `#extends`: template `{parent}` not found", pos.line, pos.col);
+        };
+        if chain.contains(&parent_origin) {
+            bail!("template inheritance cycle: {} -> {parent_origin}", chain.join(" -> "));
+        }
+        chain.push(parent_origin);
+        nodes = parent_nodes;
+    }
+    Ok(engine::apply_blocks(nodes, &overrides))
+}
+
+/// Parse the body of the template `name` refers to: a file template (its front matter is skipped),
+/// else a config template.
+fn load_named(name: &str) -> Result<Option<(String, Vec<engine::Node>)>> {
+    if let Some((path, _)) = find_template(name)?.filter(|(p, _)| p.is_file()) {
+        let origin = path.display().to_string();
+        let data = fs::read_to_string(&path).with_context(|| format!("reading template {origin}"))?;
+        let split = frontmatter::split(&data).with_context(|| format!("in template {origin}"))?;
+        let nodes = engine::parse(split.body, Syntax::ANGLE, split.body_line).with_context(|| format!("in {origin}"))?;
+        let mut nodes = include_partials(nodes, &mut vec![origin.clone()]).with_context(|| format!("in {origin}"))?;
+        engine::set_origin(&mut nodes, &origin);
+        return Ok(Some((origin, nodes)));
+    }
+    let cfg = Config::load_default()?;
+    let Some(t) = cfg.templates.get(name) else { return Ok(None) };
+    let origin = Source::Config(name.to_string()).to_string();
+    let nodes = engine::parse(&t.body, Syntax::BOTH, 1).with_context(|| format!("in {origin}"))?;
+    let mut nodes = include_partials(nodes, &mut vec![origin.clone()]).with_context(|| format!("in {origin}"))?;
+    engine::set_origin(&mut nodes, &origin);
+    Ok(Some((origin, nodes)))
+}
+
+/// Find partial `name` in `partials/` on the search path: an exact file name, else a file stem.
+fn find_partial(name: &str) -> Option<PathBuf> {
+    let dirs: Vec<PathBuf> = search_path().into_iter().map(|(_, dir)| dir.join(PARTIALS)).collect();
+    if let Some(p) = dirs.iter().map(|d| d.join(name)).find(|p| p.is_file()) { return Some(p); }
+    let (sub, stem) = name.rsplit_once('/').unwrap_or(("", name));
+    dirs.iter().find_map(|d| {
+        let mut paths: Vec<PathBuf> = fs::read_dir(d.join(sub)).ok()?.flatten().map(|e| e.path()).filter(|p| p.is_file()).collect();
+        paths.sort();
+        paths.into_iter().find(|p| p.file_stem().and_then(|s| s.to_str()) == Some(stem))
+    })
+}
+
+/// Inline every `>NAME` include, recursively. `chain` starts with the including template and holds
+/// the partials currently being expanded, for cycle errors.
+fn include_partials(nodes: Vec<engine::Node>, chain: &mut Vec<String>) -> Result<Vec<engine::Node>> {
+    engine::expand_includes(nodes, &mut |name, pos| {
+        let Some(path) = find_partial(name) else {
+            bail!("{}:{}: partial `{name}` not found in `{PARTIALS}/` on the template search path", pos.line, pos.col);
+        };
+        let origin = path.display().to_string();
+        if chain[1..].contains(&origin) {
+            bail!("include cycle: {} -> {origin}", chain.join(" -> "));
+        }
+        let src = fs::read_to_string(&path).with_context(|| format!("reading partial {origin}"))?;
+        let nodes = engine::parse(&src, Syntax::ANGLE, 1).with_context(|| format!("in partial {origin}"))?;
+        if engine::extends(&nodes).is_some() { bail!("in partial {origin}: `#extends` is only allowed in templates"); }
+        chain.push(origin.clone());
+        let nodes = include_partials(nodes, chain).with_context(|| format!("in partial {origin}"));
+        chain.pop();
+        let mut nodes = nodes?;
+        engine::set_origin(&mut nodes, &origin);
+        Ok(nodes)
+    })
+}
+
+/// Render the content for `path` in memory, without touching the file.
+/// Stdin wins over the resolved template. With `apply_placeholders` off, Lua is not run and file
+/// templates and stdin are copied verbatim; config bodies are still rendered.
+pub fn render_file(
+    path: &Path,
+    template: Option<&Resolved>,
+    stdin: Option<&str>,
+    ctx: &ContextVars,
+    apply_placeholders: bool,
+    verbose: bool,
+) -> Result<Rendered> {
+    let ctx = ctx.clone().with_template(template.filter(|_| stdin.is_none()).map(Resolved::name));
+    let mut map = template_vars(&ctx, apply_placeholders)?;
+
+    if let Some(s) = stdin {
+        if !apply_placeholders { return Ok(Rendered { content: s.to_string(), ..Default::default() }); }
+        return render_source("<stdin>", s, Syntax::ANGLE, &mut map, true);
     }
 
-    Ok(None)
+    let Some(template) = template else { return Ok(Rendered::default()) };
+    let origin = template.source().to_string();
+    if verbose { eprintln!("Template applied: {origin}"); }
+
+    match template {
+        Resolved::Config { template: t, .. } => {
+            // Config bodies also accept the `{key}` syntax
+            let mut rendered = render_source(&origin, &t.body, Syntax::BOTH, &mut map, true)?;
+            rendered.mode = t.mode.clone();
+            Ok(rendered)
+        }
+        Resolved::Scaffold(dir) => bail!("template {} is a directory scaffold and cannot render a single file", dir.display()),
+        Resolved::File(tpl) => {
+            let data = fs::read_to_string(tpl).with_context(|| format!("reading template {origin}"))?;
+            let split = frontmatter::split(&data).with_context(|| format!("in template {origin}"))?;
+            let meta = split.meta.unwrap_or_default();
+
+            let mut rendered = if apply_placeholders {
+                let prompts = meta.apply_defaults(&mut map);
+                prompt::fill(&prompts, &mut map).with_context(|| format!("in template {origin}"))?;
+                meta.coerce(&mut map).with_context(|| format!("in template {origin}"))?;
+                let rendered = render_source_from(&origin, split.body, split.body_line, Syntax::ANGLE, &mut map, true)?;
+                if verbose { eprintln!("Processed placeholders for {}", path.display()); }
+                rendered
+            } else {
+                if verbose { eprintln!("Skipped placeholders for {}", path.display()); }
+                Rendered { content: split.body.to_string(), ..Default::default() }
+            };
+
+            if let Some(pattern) = &meta.output {
+                let out = render_source(&format!("output path of {origin}"), pattern, Syntax::ANGLE, &mut map, apply_placeholders)?;
+                rendered.unresolved.extend(out.unresolved);
+                rendered.output = Some(output_path(&origin, out.content.trim())?);
+            }
+            rendered.mode = meta.mode.clone().or_else(|| executable_mode(tpl));
+            Ok(rendered)
+        }
+    }
 }
 
-pub fn apply_template_and_placeholders(path: &Path, template_file: Option<&PathBuf>, apply_placeholders: bool, verbose: bool) -> Result<()> {
-    // Fast path: if we have a template file, load it and (optionally) apply placeholders in-memory, then write once.
-    if let Some(tpl) = template_file {
-        let mut data = fs::read_to_string(tpl).with_context(|| format!("reading template {}", tpl.display()))?;
-        if verbose { eprintln!("Template applied: {}", tpl.display()); }
-        if apply_placeholders {
-            let mut map = placeholder::builtins_for(path);
-            for (k, v) in placeholder::lua_placeholders_cached().iter() { map.insert(k.clone(), v.clone()); }
-            data = placeholder::apply_placeholders(data, &map);
-        } else if verbose {
-            eprintln!("Skipped placeholders for {}", path.display());
+/// Check a front matter `output` path: it must be relative and stay inside the target's directory.
+fn output_path(origin: &str, out: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in Path::new(out).components() {
+        match part {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if path.pop() => {}
+            Component::ParentDir => bail!("in template {origin}: output path `{out}` leaves the target's directory"),
+            Component::RootDir | Component::Prefix(_) => bail!("in template {origin}: output path `{out}` must be relative"),
         }
-        fs::write(path, data).with_context(|| format!("writing {}", path.display()))?;
-        if apply_placeholders && verbose { eprintln!("Processed placeholders for {}", path.display()); }
-        return Ok(());
     }
+    if path.as_os_str().is_empty() { bail!("in template {origin}: output path `{out}` does not name a file"); }
+    Ok(path)
+}
 
-    // Otherwise, only placeholders (if enabled) against whatever content is already on disk
-    if apply_placeholders {
-        let mut map = placeholder::builtins_for(path);
-        for (k, v) in placeholder::lua_placeholders_cached().iter() { map.insert(k.clone(), v.clone()); }
-        let content = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
-        let updated = placeholder::apply_placeholders(content, &map);
-        fs::write(path, updated).with_context(|| format!("writing {}", path.display()))?;
-        if verbose { eprintln!("Processed placeholders for {}", path.display()); }
-    } else if verbose {
-        eprintln!("Skipped placeholders for {}", path.display());
+/// The bytes of `path` when it is not UTF-8 text (an image, a font), else `None`.
+pub fn binary_content(path: &Path) -> Result<Option<Vec<u8>>> {
+    let data = fs::read(path).with_context(|| format!("reading template {}", path.display()))?;
+    Ok(std::str::from_utf8(&data).is_err().then_some(data))
+}
+
+/// The template's permission bits as octal, if any execute bit is set, so `fs::write`'s default
+/// mode does not drop them.
+#[cfg(unix)]
+pub fn executable_mode(tpl: &Path) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(tpl).ok()?.permissions().mode() & 0o777;
+    (mode & 0o111 != 0).then(|| format!("{mode:o}"))
+}
+
+#[cfg(not(unix))]
+pub fn executable_mode(_tpl: &Path) -> Option<String> { None }
+
+/// Report unresolved tokens according to `mode`. In [`StrictMode::Error`] this fails so callers can
+/// abort before writing anything.
+pub fn check_unresolved(tokens: &[UnresolvedToken], mode: StrictMode) -> Result<()> {
+    if tokens.is_empty() || mode == StrictMode::Off { return Ok(()); }
+    // The same template may be rendered for several targets; list each token once
+    let mut lines: Vec<String> = Vec::new();
+    for t in tokens {
+        let line = format!("  {}:{}:{}: {}", t.origin, t.token.pos.line, t.token.pos.col, t.token.token);
+        if !lines.contains(&line) { lines.push(line); }
     }
+    let list = lines.join("\n");
+    if mode == StrictMode::Error {
+        return Err(MkError::Unresolved(list).into());
+    }
+    eprintln!("mk: warning: unresolved placeholders:\n{list}");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_match_names_extensions_and_patterns() {
+        let reason = |entry: &str, name: &str| entry_match(entry, name).unwrap();
+        assert_eq!(reason("Dockerfile", "Dockerfile"), Some(MatchReason::Pattern("Dockerfile".into())));
+        assert_eq!(reason("ts", "app.ts"), Some(MatchReason::Extension("ts".into())));
+        assert_eq!(reason("ts", "app.tsx"), None);
+        assert_eq!(reason("*.test.ts", "app.test.ts"), Some(MatchReason::Pattern("*.test.ts".into())));
+        assert_eq!(reason("re:^Makefile(\\..+)?$", "Makefile.am"), Some(MatchReason::Pattern("re:^Makefile(\\..+)?$".into())));
+        assert!(entry_match("re:[", "x").is_err());
+
+        let rank = |entry: &str| reason(entry, "app.test.ts").unwrap().specificity();
+        assert!(rank("*.test.ts") > rank("ts"));
+        assert!(rank("app.test.ts") > rank("*.test.ts"));
+        assert!(rank("ts") > rank("re:\\.ts$"));
+    }
+
+    #[test]
+    fn output_paths_stay_inside_the_target_directory() {
+        assert_eq!(output_path("t", "Widget/index.tsx").unwrap(), Path::new("Widget/index.tsx"));
+        assert_eq!(output_path("t", "./a/../b.rs").unwrap(), Path::new("b.rs"));
+        for bad in ["../up.rs", "a/../../up.rs", "/etc/passwd", ".", ""] {
+            assert!(output_path("t", bad).is_err(), "`{bad}` was accepted");
+        }
+    }
+
+    #[test]
+    fn expanded_names_stay_one_segment() {
+        let vars = |v: &str| -> Vars { [("NAME".to_string(), v.into())].into() };
+        assert_eq!(expand_name("__NAME|snake__.rs", &vars("MyCrate")).unwrap(), "my_crate.rs");
+        assert_eq!(expand_name("__init__.py", &vars("x")).unwrap(), "__init__.py");
+        for bad in ["../escaped", "..", "/etc", "a/b", "a\\b", ""] {
+            assert!(expand_name("__NAME__", &vars(bad)).is_err(), "`{bad}` was accepted");
+        }
+    }
+}